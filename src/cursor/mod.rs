@@ -1,10 +1,5 @@
 pub mod string_cursor;
 
-use crate::{
-    parser::parse_error::ParseError::{self, *},
-    scanner::{token::Token, token_kind::TokenKind},
-};
-
 pub struct Cursor<T: Clone> {
     source: Vec<T>,
     position: usize,
@@ -39,6 +34,11 @@ impl<T: Clone> Cursor<T> {
         self.source.get(self.position + 1).cloned()
     }
 
+    /// Returns the value `offset` positions ahead, or None past the end.
+    pub fn peek(&self, offset: usize) -> Option<T> {
+        self.source.get(self.position + offset).cloned()
+    }
+
     /// Returns true if the cursor is at the end of the source.
     pub fn is_done(&self) -> bool {
         self.source.get(self.position).is_none()
@@ -72,25 +72,3 @@ impl<T: Clone> Cursor<T> {
         eaten
     }
 }
-
-impl Cursor<Token> {
-    /// Eats the current token if it is of the specified kind, returning it.
-    /// Otherwise, returns an error.
-    pub fn eat_kind(&mut self, kind: TokenKind) -> Result<Token, ParseError> {
-        let current = self.current().ok_or(ExpectedToken {
-            expected: kind,
-            got: None,
-            line: self.prev().map(|t| t.line).unwrap_or(0),
-        })?;
-
-        if current.kind == kind {
-            Ok(self.eat().expect("Should be Some"))
-        } else {
-            Err(ExpectedToken {
-                expected: kind,
-                got: Some(current.kind),
-                line: 0,
-            })
-        }
-    }
-}
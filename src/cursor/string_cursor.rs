@@ -1,19 +1,33 @@
-use crate::cursor::Cursor;
+use crate::{cursor::Cursor, scanner::span::Span};
 
 pub const EOF: char = '\0';
 
+#[derive(Clone, Copy)]
+struct Position {
+	// In bytes, so that spans can be used to slice the source.
+	offset: usize,
+	line: usize,
+	column: usize,
+}
+
 pub struct StringCursor {
 	cursor: Cursor<char>,
-	checkpoint: usize,
-	line: usize,
+	position: Position,
+	checkpoint: Position,
 }
 
 impl StringCursor {
 	pub fn new(source: String) -> Self {
+		let start = Position {
+			offset: 0,
+			line: 1,
+			column: 1,
+		};
+
 		Self {
 			cursor: Cursor::new(source.chars().collect()),
-			checkpoint: 0,
-			line: 1,
+			position: start,
+			checkpoint: start,
 		}
 	}
 
@@ -34,7 +48,12 @@ impl StringCursor {
 
     /// Returns the current line number.
 	pub fn line(&self) -> usize {
-		self.line
+		self.position.line
+	}
+
+    /// Returns the current column number, counted in characters.
+	pub fn column(&self) -> usize {
+		self.position.column
 	}
 
     /// Returns true if the cursor is at the end of the source.
@@ -44,7 +63,36 @@ impl StringCursor {
 
     /// Sets a checkpoint at the current cursor position.
 	pub fn set_checkpoint(&mut self) {
-		self.cursor.set_checkpoint()
+		self.cursor.set_checkpoint();
+		self.checkpoint = self.position;
+	}
+
+    /// Returns the span of every char eaten since the last checkpoint.
+	pub fn span_since_checkpoint(&self) -> Span {
+		Span {
+			start: self.checkpoint.offset,
+			end: self.position.offset,
+			line: self.checkpoint.line,
+			column: self.checkpoint.column,
+		}
+	}
+
+    /// Returns an empty span at the current cursor position.
+	pub fn span_here(&self) -> Span {
+		Span {
+			start: self.position.offset,
+			end: self.position.offset,
+			line: self.position.line,
+			column: self.position.column,
+		}
+	}
+
+    /// Returns the span from the start of `start` up to the current cursor position.
+	pub fn span_since(&self, start: Span) -> Span {
+		Span {
+			end: self.position.offset,
+			..start
+		}
 	}
 
     /// Returns every char eaten since the last checkpoint.
@@ -60,8 +108,13 @@ impl StringCursor {
     /// Eats the current character and returns it. Returns EOF if the cursor is at the end of the source.
 	pub fn eat(&mut self) -> char {
 		if let Some(c) = self.cursor.eat() {
+			self.position.offset += c.len_utf8();
+
 			if c == '\n' {
-				self.line += 1;
+				self.position.line += 1;
+				self.position.column = 1;
+			} else {
+				self.position.column += 1;
 			}
 
 			return c;
@@ -72,7 +125,14 @@ impl StringCursor {
 
     /// Repeatedly eats characters while the predicate returns true. Returns the characters eaten.
 	pub fn eat_while(&mut self, predicate: impl Fn(char) -> bool) -> Vec<char> {
-		self.cursor.eat_while(predicate)
+		let mut eaten = Vec::new();
+
+		// Eaten one by one so that the position is kept up to date.
+		while !self.is_eof() && predicate(self.current()) {
+			eaten.push(self.eat());
+		}
+
+		eaten
 	}
 }
 
@@ -111,4 +171,17 @@ mod tests {
         cursor.eat();
         assert!(cursor.is_eof());
     }
+
+    #[test]
+    fn test_span() {
+        let mut cursor = StringCursor::new("ab\nçd".into());
+
+        cursor.eat_while(|c| c != 'd');
+        cursor.set_checkpoint();
+        cursor.eat();
+
+        let span = cursor.span_since_checkpoint();
+        assert_eq!((span.start, span.end), (5, 6));
+        assert_eq!((span.line, span.column), (2, 2));
+    }
 }
@@ -0,0 +1,68 @@
+use crate::scanner::span::Span;
+
+/// Renders a rustc-style diagnostic: the message, where it happened, and the source line
+/// it happened in with the span underlined. For example:
+/// ```text
+/// error: Unexpected character `@`
+///  --> 1:5
+///   |
+/// 1 | var @ = 1;
+///   |     ^
+/// ```
+/// If the span does not point into `source`, only the message and location are rendered.
+pub fn render(source: &str, span: Span, message: &str) -> String {
+    let mut out = format!("error: {message}\n");
+    let gutter = " ".repeat(span.line.to_string().len());
+
+    out += &format!("{gutter}--> {}:{}", span.line, span.column);
+
+    let Some(before) = source.get(..span.start) else {
+        return out;
+    };
+
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+    let line_end = source[span.start..]
+        .find('\n')
+        .map_or(source.len(), |i| span.start + i);
+    let line = source[line_start..line_end].trim_end_matches('\r');
+
+    // Tabs are kept as is so that the underline lines up however wide they are displayed.
+    let padding: String = source[line_start..span.start]
+        .chars()
+        .map(|c| if c == '\t' { '\t' } else { ' ' })
+        .collect();
+
+    // Spans across multiple lines are only underlined up to the end of the first one.
+    let width = source
+        .get(span.start..span.end.min(line_end))
+        .map_or(0, |s| s.chars().count())
+        .max(1);
+
+    out += &format!("\n{gutter} |\n");
+    out += &format!("{} | {line}\n", span.line);
+    out += &format!("{gutter} | {padding}{}", "^".repeat(width));
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::render;
+    use crate::scanner::span::Span;
+
+    #[test]
+    fn test_render() {
+        let source = "print 1;\nvar @ = 1;\n";
+        let span = Span {
+            start: 13,
+            end: 14,
+            line: 2,
+            column: 5,
+        };
+
+        assert_eq!(
+            render(source, span, "Unexpected character `@`"),
+            "error: Unexpected character `@`\n --> 2:5\n  |\n2 | var @ = 1;\n  |     ^"
+        );
+    }
+}
@@ -1,25 +1,64 @@
 use std::fmt;
 
 use crate::{
-    interpreter::runtime_error::RuntimeError, parser::parse_error::ParseError,
-    scanner::scan_error::ScanError,
+    diagnostic, interpreter::runtime_error::RuntimeError, parser::parse_error::ParseError,
+    resolver::resolve_error::ResolveError, scanner::scan_error::ScanError,
+    vm::compile_error::CompileError,
 };
 
 pub enum Error {
     Scan(ScanError),
-    Parse(ParseError),
+    Parse(Vec<ParseError>),
+    Resolve(ResolveError),
+    Compile(CompileError),
     Runtime(RuntimeError),
 }
 
+impl Error {
+    /// Renders every error as a diagnostic showing where it happened in `source`.
+    pub fn render(&self, source: &str) -> String {
+        let rendered: Vec<String> = match self {
+            Error::Scan(err) => vec![diagnostic::render(source, err.span(), &err.message())],
+            Error::Parse(errs) => errs
+                .iter()
+                .map(|err| diagnostic::render(source, err.span(), &err.message()))
+                .collect(),
+            Error::Resolve(err) => vec![diagnostic::render(source, err.span(), &err.message())],
+            Error::Compile(err) => vec![diagnostic::render(source, err.span(), &err.message())],
+            Error::Runtime(err) => {
+                let mut rendered = diagnostic::render(source, err.span(), &err.message());
+                if !err.trace.is_empty() {
+                    rendered += &format!("\n{}", err.traceback());
+                }
+                vec![rendered]
+            }
+        };
+
+        rendered.join("\n\n")
+    }
+}
+
 impl From<ScanError> for Error {
     fn from(err: ScanError) -> Self {
         Error::Scan(err)
     }
 }
 
-impl From<ParseError> for Error {
-    fn from(err: ParseError) -> Self {
-        Error::Parse(err)
+impl From<Vec<ParseError>> for Error {
+    fn from(errs: Vec<ParseError>) -> Self {
+        Error::Parse(errs)
+    }
+}
+
+impl From<ResolveError> for Error {
+    fn from(err: ResolveError) -> Self {
+        Error::Resolve(err)
+    }
+}
+
+impl From<CompileError> for Error {
+    fn from(err: CompileError) -> Self {
+        Error::Compile(err)
     }
 }
 
@@ -33,7 +72,18 @@ impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             Error::Scan(err) => write!(f, "{err}"),
-            Error::Parse(err) => write!(f, "{err}"),
+            Error::Parse(errs) => {
+                for (i, err) in errs.iter().enumerate() {
+                    if i > 0 {
+                        writeln!(f)?;
+                    }
+                    write!(f, "{err}")?;
+                }
+
+                Ok(())
+            }
+            Error::Resolve(err) => write!(f, "{err}"),
+            Error::Compile(err) => write!(f, "{err}"),
             Error::Runtime(err) => write!(f, "{err}"),
         }
     }
@@ -0,0 +1,15 @@
+use super::{runtime_error::RuntimeError, value::Value};
+use crate::scanner::span::Span;
+
+/// Anything that can be called with a list of arguments, such as functions.
+pub trait Callable {
+    /// The name the callable is reported with in tracebacks.
+    fn name(&self) -> &str;
+
+    /// The amount of arguments the callable expects.
+    fn arity(&self) -> usize;
+
+    /// Calls with already evaluated arguments.
+    /// `args` is expected to have exactly `arity` elements and `span` is where the call happens.
+    fn call(&self, args: Vec<Value>, span: Span) -> Result<Value, RuntimeError>;
+}
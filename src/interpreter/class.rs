@@ -0,0 +1,126 @@
+use std::{cell::RefCell, collections::HashMap, fmt, rc::Rc};
+
+use super::{
+    callable::Callable,
+    function::Function,
+    runtime_error::{undefined_property, RuntimeError},
+    value::Value,
+};
+use crate::scanner::{span::Span, token::Token};
+
+/// The name of the method called when a class is instantiated.
+pub const INITIALIZER: &str = "init";
+
+pub struct Class {
+    name: String,
+    superclass: Option<Rc<Class>>,
+    methods: HashMap<String, Rc<Function>>,
+}
+
+impl Class {
+    pub fn new(
+        name: String,
+        superclass: Option<Rc<Class>>,
+        methods: HashMap<String, Rc<Function>>,
+    ) -> Self {
+        Self {
+            name,
+            superclass,
+            methods,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns the unbound method called `name`, looking it up in the superclass chain
+    /// if this class does not define it.
+    pub fn find_method(&self, name: &str) -> Option<Rc<Function>> {
+        match self.methods.get(name) {
+            Some(method) => Some(Rc::clone(method)),
+            None => self.superclass.as_ref()?.find_method(name),
+        }
+    }
+}
+
+// Implemented for `Rc<Class>` rather than `Class` because instances must hold
+// on to the class that created them.
+impl Callable for Rc<Class> {
+    fn name(&self) -> &str {
+        Class::name(self)
+    }
+
+    fn arity(&self) -> usize {
+        self.find_method(INITIALIZER).map_or(0, |init| init.arity())
+    }
+
+    fn call(&self, args: Vec<Value>, span: Span) -> Result<Value, RuntimeError> {
+        let instance = Rc::new(RefCell::new(Instance::new(Rc::clone(self))));
+
+        if let Some(init) = self.find_method(INITIALIZER) {
+            init.bind(&instance).call(args, span)?;
+        }
+
+        Ok(Value::Instance(instance))
+    }
+}
+
+impl fmt::Debug for Class {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "<class {}>", self.name)
+    }
+}
+
+impl fmt::Display for Class {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self, f)
+    }
+}
+
+pub struct Instance {
+    class: Rc<Class>,
+    fields: HashMap<String, Value>,
+}
+
+impl Instance {
+    pub fn new(class: Rc<Class>) -> Self {
+        Self {
+            class,
+            fields: HashMap::new(),
+        }
+    }
+
+    /// Returns the field called `name` or, if there is none, the method with that name
+    /// bound to `this`.
+    /// Errors if neither exist.
+    pub fn get(this: &Rc<RefCell<Self>>, name: &Token) -> Result<Value, RuntimeError> {
+        let instance = this.borrow();
+
+        if let Some(value) = instance.fields.get(&name.lexeme) {
+            return Ok(value.clone());
+        }
+
+        match instance.class.find_method(&name.lexeme) {
+            Some(method) => Ok(Value::Function(Rc::new(method.bind(this)))),
+            None => Err(undefined_property(name.clone())),
+        }
+    }
+
+    /// Creates or overwrites the field called `name`.
+    pub fn set(&mut self, name: &Token, value: Value) {
+        self.fields.insert(name.lexeme.clone(), value);
+    }
+}
+
+impl fmt::Debug for Instance {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "<{} instance>", self.class.name)
+    }
+}
+
+impl fmt::Display for Instance {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self, f)
+    }
+}
@@ -1,14 +1,20 @@
-use std::{
-    cell::RefCell,
-    collections::{hash_map::Entry, HashMap},
-    rc::Rc,
-};
+use std::{cell::RefCell, collections::HashMap, rc::Rc};
 
-use super::runtime_error::{undefined_variable, RuntimeError};
-use crate::scanner::{literal::Literal, token::Token};
+use super::{
+    runtime_error::{undefined_variable, RuntimeError},
+    value::Value,
+};
+use crate::{parser::expr::Local, scanner::token::Token};
 
+/// An environment holding variables, either the global one or the one of a function call.
+/// Blocks mostly define their variables in the environment they run in, and drop them when left.
+/// Locals are stored by the slot the resolver gave them, while globals are looked up by name
+/// since they can be used before being declared.
 pub struct Env {
-    bindings: HashMap<String, Literal>,
+    // Always empty in local scopes.
+    globals: HashMap<String, Value>,
+    // Always empty in the global scope.
+    slots: Vec<Value>,
     enclosing: Option<Rc<RefCell<Env>>>,
 }
 
@@ -16,7 +22,8 @@ impl Env {
     /// Returns an environment with no parent, aka global.
     pub fn new() -> Rc<RefCell<Self>> {
         Rc::new(RefCell::new(Self {
-            bindings: HashMap::new(),
+            globals: HashMap::new(),
+            slots: Vec::new(),
             enclosing: None,
         }))
     }
@@ -24,41 +31,83 @@ impl Env {
     /// Returns an environment with a parent.
     pub fn new_enclosed(enclosing: &Rc<RefCell<Env>>) -> Rc<RefCell<Self>> {
         Rc::new(RefCell::new(Self {
-            bindings: HashMap::new(),
+            globals: HashMap::new(),
+            slots: Vec::new(),
             enclosing: Some(Rc::clone(enclosing)),
         }))
     }
 
-    /// Returns the value bound to ´name´ in the current or above scopes.
+    /// Runs `f` on the environment `depth` scopes above `env`.
+    /// The scopes in between are only borrowed on the way up.
+    pub fn at<R>(env: &Rc<RefCell<Env>>, depth: usize, f: impl FnOnce(&mut Env) -> R) -> R {
+        if depth == 0 {
+            return f(&mut env.borrow_mut());
+        }
+
+        match &env.borrow().enclosing {
+            Some(enclosing) => Env::at(enclosing, depth - 1, f),
+            None => panic!("Resolved depth {} goes past the global scope", depth),
+        }
+    }
+
+    /// Runs `f` on the global environment `env` is nested in.
+    pub fn global<R>(env: &Rc<RefCell<Env>>, f: impl FnOnce(&mut Env) -> R) -> R {
+        if let Some(enclosing) = &env.borrow().enclosing {
+            return Env::global(enclosing, f);
+        }
+
+        f(&mut env.borrow_mut())
+    }
+
+    /// Returns the value of the resolved variable `local`, as seen from `env`.
+    pub fn get_local(env: &Rc<RefCell<Env>>, local: Local) -> Value {
+        Env::at(env, local.depth, |scope| scope.slots[local.slot].clone())
+    }
+
+    /// Assigns to the resolved variable `local`, as seen from `env`, returning the old value.
+    pub fn assign_local(env: &Rc<RefCell<Env>>, local: Local, value: Value) -> Value {
+        Env::at(env, local.depth, |scope| {
+            std::mem::replace(&mut scope.slots[local.slot], value)
+        })
+    }
+
+    /// Returns how many locals are defined here.
+    pub fn local_count(&self) -> usize {
+        self.slots.len()
+    }
+
+    /// Drops every local defined after the first `count`, such as those of a block being left.
+    pub fn truncate_locals(&mut self, count: usize) {
+        self.slots.truncate(count);
+    }
+
+    /// Returns the value bound to ´name´ in this global scope.
     /// Errors if binding could not be found.
-    pub fn get(&self, name: Token) -> Result<Literal, RuntimeError> {
-        if let Some(value) = self.bindings.get(&name.lexeme) {
-            Ok(value.clone())
-        } else if let Some(enclosing) = &self.enclosing {
-            enclosing.borrow().get(name)
-        } else {
-            Err(undefined_variable(name))
+    pub fn get(&self, name: &Token) -> Result<Value, RuntimeError> {
+        match self.globals.get(&name.lexeme) {
+            Some(value) => Ok(value.clone()),
+            None => Err(undefined_variable(name.clone())),
         }
     }
 
-    /// Defines a new binding or overwrites the old one, returning it.
-    pub fn define(&mut self, name: Token, value: Literal) -> Option<Literal> {
-        self.bindings.insert(name.lexeme, value)
+    /// Defines a new binding. Locals take the next slot, so they must be defined in
+    /// the order the resolver saw them. Globals overwrite any old binding, which is returned.
+    pub fn define(&mut self, name: &Token, value: Value) -> Option<Value> {
+        if self.enclosing.is_some() {
+            self.slots.push(value);
+            return None;
+        }
+
+        self.globals.insert(name.lexeme.clone(), value)
     }
 
-    /// Assigns a value to an already existing binding in the current or above scopes,
+    /// Assigns a value to an already existing binding in this global scope,
     /// returning the old value.
     /// Errors if binding could not be found.
-    pub fn assign(&mut self, name: Token, value: Literal) -> Result<Literal, RuntimeError> {
-        match self.bindings.entry(name.lexeme.clone()) {
-            Entry::Occupied(mut entry) => {
-                let old = entry.insert(value);
-                Ok(old)
-            }
-            Entry::Vacant(_) => match &self.enclosing {
-                Some(enclosing) => enclosing.borrow_mut().assign(name, value),
-                None => Err(undefined_variable(name)),
-            },
+    pub fn assign(&mut self, name: &Token, value: Value) -> Result<Value, RuntimeError> {
+        match self.globals.get_mut(&name.lexeme) {
+            Some(global) => Ok(std::mem::replace(global, value)),
+            None => Err(undefined_variable(name.clone())),
         }
     }
 }
@@ -67,20 +116,29 @@ mod tests {
     use std::{cell::RefCell, rc::Rc};
 
     use super::Env;
-    use crate::scanner::{literal::Literal, token::Token, token_kind::TokenKind as TK};
+    use crate::{
+        interpreter::value::Value,
+        parser::expr::Local,
+        scanner::{span::Span, token::Token, token_kind::TokenKind as TK},
+    };
 
     #[test]
     fn test() {
-        let and = Token::symbol(TK::And, "and".into(), 1);
-        let one = Literal::Number(1.0);
-        let two = Literal::Number(2.0);
-        let three = Literal::Number(3.0);
+        let and = Token::symbol(TK::And, "and".into(), Span::default());
+        let one = Value::Number(1.0);
+        let two = Value::Number(2.0);
+        let three = Value::Number(3.0);
         let global = Env::new();
         let child = Env::new_enclosed(&global);
+        let grandchild = Env::new_enclosed(&child);
 
-        global.borrow_mut().define(and.clone(), one.clone());
-        let _ = child.borrow_mut().assign(and.clone(), two.clone());
+        global.borrow_mut().define(&and, one.clone());
+        let _ = global.borrow_mut().assign(&and, two.clone());
+        assert_eq!(Env::global(&grandchild, |global| global.get(&and)).unwrap(), two);
 
-        assert_eq!(global.borrow().get(and.clone()).unwrap(), two);
+        child.borrow_mut().define(&and, one.clone());
+        let local = Local { depth: 1, slot: 0 };
+        assert_eq!(Env::assign_local(&grandchild, local, three.clone()), one);
+        assert_eq!(Env::get_local(&grandchild, local), three);
     }
 }
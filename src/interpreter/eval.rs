@@ -1,144 +1,335 @@
-use std::{cell::RefCell, rc::Rc};
+use std::{cell::RefCell, collections::HashMap, rc::Rc};
 
 use super::{
+    callable::Callable,
+    class::Instance,
     env::Env,
+    function::Function,
+    ops,
     runtime_error::{self, *},
+    value::Value,
 };
 use crate::{
-    parser::expr::Expr,
-    scanner::{literal::Literal, token_kind::TokenKind as TK},
+    parser::expr::{Expr, Local},
+    scanner::{token::Token, token_kind::TokenKind as TK},
 };
 
 /// Evaluates a single expression tree and returns the resulting literal.
 /// Evaluation can contain side effects, just like executions.
 /// This is the expression analogue of `execute`.
-pub fn eval(expr: Expr, env: Rc<RefCell<Env>>) -> Result<Literal, RuntimeError> {
+pub fn eval(expr: &Expr, env: &Rc<RefCell<Env>>) -> Result<Value, RuntimeError> {
     match expr {
-        Expr::Literal(literal) => Ok(literal.clone()),
+        Expr::Literal(literal) => Ok(literal.into()),
 
         Expr::Unary(op, r) => {
-            let r = eval(*r, env)?;
-
-            match op.kind {
-                TK::Minus => match r {
-                    Literal::Number(n) => Ok(Literal::Number(-n)),
-
-                    _ => Err(bad_un_op(op.kind, r, op.line)),
-                },
-
-                _ => panic!("Invalid unary operator `{:?}`", op.kind),
-            }
+            let r = eval(r, env)?;
+            ops::unary(op.kind, r, op.span)
         }
 
         Expr::Binary(l, op, r) => {
-            let l = eval(*l, env.clone())?;
-            let r = eval(*r, env)?;
-
-            match op.kind {
-                TK::Plus => match (&l, &r) {
-                    (Literal::Number(l), Literal::Number(r)) => Ok(Literal::Number(l + r)),
-
-                    // "foo" + "bar", "foo" + 1, ...
-                    (Literal::String(l), r) => Ok(Literal::String(format!("{}{}", l, r))),
-
-                    // "foo" + "bar", 1 + "bar", ...
-                    (l, Literal::String(r)) => Ok(Literal::String(format!("{}{}", l, r))),
-
-                    _ => Err(bad_bin_ops(op.kind, l, r, op.line)),
-                },
-
-                TK::Minus => match (&l, &r) {
-                    (Literal::Number(l), Literal::Number(r)) => Ok(Literal::Number(l - r)),
-                    _ => Err(bad_bin_ops(op.kind, l, r, op.line)),
-                },
-
-                TK::Star => match (&l, &r) {
-                    (Literal::Number(l), Literal::Number(r)) => Ok(Literal::Number(l * r)),
-                    _ => Err(bad_bin_ops(op.kind, l, r, op.line)),
-                },
-
-                TK::Slash => match (&l, &r) {
-                    (Literal::Number(l), Literal::Number(r)) => {
-                        if r == &0. {
-                            return Err(div_by_zero(Literal::Number(*l), op.line));
-                        }
-
-                        Ok(Literal::Number(l / r))
-                    }
-
-                    _ => Err(bad_bin_ops(op.kind, l, r, op.line)),
-                },
-
-                TK::Greater => match (&l, &r) {
-                    (Literal::Number(l), Literal::Number(r)) => Ok(Literal::Bool(l > r)),
-                    _ => Err(bad_bin_ops(op.kind, l, r, op.line)),
-                },
-
-                TK::GreaterEqual => match (&l, &r) {
-                    (Literal::Number(l), Literal::Number(r)) => Ok(Literal::Bool(l >= r)),
-                    _ => Err(bad_bin_ops(op.kind, l, r, op.line)),
-                },
-
-                TK::Less => match (&l, &r) {
-                    (Literal::Number(l), Literal::Number(r)) => Ok(Literal::Bool(l < r)),
-                    _ => Err(bad_bin_ops(op.kind, l, r, op.line)),
-                },
-
-                TK::LessEqual => match (&l, &r) {
-                    (Literal::Number(l), Literal::Number(r)) => Ok(Literal::Bool(l <= r)),
-                    _ => Err(bad_bin_ops(op.kind, l, r, op.line)),
-                },
-
-                TK::EqualEqual => match (&l, &r) {
-                    (Literal::Number(l), Literal::Number(r)) => Ok(Literal::Bool(l == r)),
-                    (Literal::String(l), Literal::String(r)) => Ok(Literal::Bool(l == r)),
-                    (Literal::Bool(l), Literal::Bool(r)) => Ok(Literal::Bool(l == r)),
-                    (Literal::Nil, Literal::Nil) => Ok(Literal::Bool(true)),
-                    _ => Err(bad_bin_ops(op.kind, l, r, op.line)),
-                },
-
-                TK::BangEqual => match (&l, &r) {
-                    (Literal::Number(l), Literal::Number(r)) => Ok(Literal::Bool(l != r)),
-                    (Literal::String(l), Literal::String(r)) => Ok(Literal::Bool(l != r)),
-                    (Literal::Bool(l), Literal::Bool(r)) => Ok(Literal::Bool(l != r)),
-                    (Literal::Nil, Literal::Nil) => Ok(Literal::Bool(false)),
-                    _ => Err(bad_bin_ops(op.kind, l, r, op.line)),
-                },
-
-                _ => panic!("Invalid binary operator `{:?}`", op.kind),
-            }
+            let l = eval(l, env)?;
+            let r = eval(r, env)?;
+            ops::binary(op.kind, l, r, op.span)
         }
 
-        Expr::Group(expr) => eval(*expr, env),
+        Expr::Group(expr) => eval(expr, env),
+
+        Expr::Interpolation(parts) => interpolation(parts, env),
 
         Expr::Ternary(expr, if_, else_) => {
-            let cond = eval(*expr, env.clone())?;
+            let cond = eval(expr, env)?;
 
             if cond.is_truthy() {
-                eval(*if_, env)
+                eval(if_, env)
             } else {
-                eval(*else_, env)
+                eval(else_, env)
             }
         }
 
-        Expr::Variable { name } => env.borrow().get(name.clone()),
+        Expr::Variable { name, local } => match local {
+            Some(local) => Ok(Env::get_local(env, *local)),
+            None => Env::global(env, |global| global.get(name)),
+        },
 
-        Expr::Assign { name, value } => {
-            let evaluated = eval(*value, env.clone())?;
-            let old = env.borrow_mut().assign(name, evaluated.clone())?;
-            Ok(evaluated)
-        }
+        Expr::Assign { name, value, local } => assign(name, value, *local, env),
+
+        Expr::Update {
+            target,
+            op,
+            value,
+            postfix,
+        } => update(target, op, value, *postfix, env),
 
         Expr::Logical(l, op, r) => {
-            let l = eval(*l, env.clone())?;
+            let l = eval(l, env)?;
 
             // Short-circuiting since the right side is only evaluated if the left side
             // is not enough to determine the result.
             match op.kind {
                 TK::Or if l.is_truthy() => Ok(l),
                 TK::And if !l.is_truthy() => Ok(l),
-                _ => eval(*r, env),
+                _ => eval(r, env),
             }
         }
+
+        Expr::Call {
+            callee,
+            paren,
+            args,
+        } => call(callee, paren, args, env),
+
+        Expr::Get { object, name } => match eval(object, env)? {
+            Value::Instance(instance) => Instance::get(&instance, name),
+            Value::Error(err) => err.property(name),
+            value => Err(not_an_instance(value, name.span)),
+        },
+
+        Expr::Set {
+            object,
+            name,
+            value,
+        } => set(object, name, value, env),
+
+        Expr::Lambda(decl) => {
+            let fun = Function::new(Rc::clone(decl), Rc::clone(env), false);
+            Ok(Value::Function(Rc::new(fun)))
+        }
+
+        Expr::List { items, .. } => list(items, env),
+
+        Expr::Map { brace, entries } => map(brace, entries, env),
+
+        Expr::Index {
+            object,
+            bracket,
+            index,
+        } => {
+            let object = eval(object, env)?;
+            let index = eval(index, env)?;
+            ops::index(object, index, bracket.span)
+        }
+
+        Expr::SetIndex {
+            object,
+            bracket,
+            index,
+            value,
+        } => set_index(object, bracket, index, value, env),
+
+        Expr::This { local, .. } => {
+            let local = local.expect("`this` should always be resolved to a local");
+            Ok(Env::get_local(env, local))
+        }
+
+        Expr::Super { method, local, .. } => {
+            let local = local.expect("`super` should always be resolved to a local");
+            super_method(method, local, env)
+        }
+    }
+}
+
+// Calls nest `eval` deeply, so most of its cases get their own functions to keep the native
+// stack it takes per level small.
+
+// Parts are stringified just like `print` does.
+fn interpolation(parts: &[Expr], env: &Rc<RefCell<Env>>) -> Result<Value, RuntimeError> {
+    let mut string = String::new();
+    for part in parts {
+        string += &eval(part, env)?.to_string();
+    }
+
+    Ok(Value::String(string))
+}
+
+fn assign(
+    name: &Token,
+    value: &Expr,
+    local: Option<Local>,
+    env: &Rc<RefCell<Env>>,
+) -> Result<Value, RuntimeError> {
+    let evaluated = eval(value, env)?;
+    match local {
+        Some(local) => Env::assign_local(env, local, evaluated.clone()),
+        None => Env::global(env, |global| global.assign(name, evaluated.clone()))?,
+    };
+    Ok(evaluated)
+}
+
+fn set(
+    object: &Expr,
+    name: &Token,
+    value: &Expr,
+    env: &Rc<RefCell<Env>>,
+) -> Result<Value, RuntimeError> {
+    let object = eval(object, env)?;
+    let value = eval(value, env)?;
+
+    match object {
+        Value::Instance(instance) => {
+            instance.borrow_mut().set(name, value.clone());
+            Ok(value)
+        }
+
+        object => Err(not_an_instance(object, name.span)),
+    }
+}
+
+fn list(items: &[Expr], env: &Rc<RefCell<Env>>) -> Result<Value, RuntimeError> {
+    let mut evaluated = Vec::with_capacity(items.len());
+    for item in items {
+        evaluated.push(eval(item, env)?);
+    }
+
+    Ok(Value::from(evaluated))
+}
+
+fn set_index(
+    object: &Expr,
+    bracket: &Token,
+    index: &Expr,
+    value: &Expr,
+    env: &Rc<RefCell<Env>>,
+) -> Result<Value, RuntimeError> {
+    let object = eval(object, env)?;
+    let index = eval(index, env)?;
+    let value = eval(value, env)?;
+    ops::set_index(object, index, value, bracket.span)
+}
+
+// Applies a compound assignment or an increment to a variable, field or element.
+fn update(
+    target: &Expr,
+    op: &Token,
+    value: &Expr,
+    postfix: bool,
+    env: &Rc<RefCell<Env>>,
+) -> Result<Value, RuntimeError> {
+    let update = |old: &Value| ops::binary(op.kind, old.clone(), eval(value, env)?, op.span);
+
+    // The parts of the target are evaluated once, then used both to read and to write.
+    let (old, new) = match target {
+        Expr::Variable { name, local } => {
+            let old = eval(target, env)?;
+            let new = update(&old)?;
+            match local {
+                Some(local) => Env::assign_local(env, *local, new.clone()),
+                None => Env::global(env, |global| global.assign(name, new.clone()))?,
+            };
+            (old, new)
+        }
+
+        Expr::Get { object, name } => {
+            let instance = match eval(object, env)? {
+                Value::Instance(instance) => instance,
+                value => return Err(not_an_instance(value, name.span)),
+            };
+            let old = Instance::get(&instance, name)?;
+            let new = update(&old)?;
+            instance.borrow_mut().set(name, new.clone());
+            (old, new)
+        }
+
+        Expr::Index {
+            object,
+            bracket,
+            index,
+        } => {
+            let object = eval(object, env)?;
+            let index = eval(index, env)?;
+            let old = ops::index(object.clone(), index.clone(), bracket.span)?;
+            let new = update(&old)?;
+            ops::set_index(object, index, new.clone(), bracket.span)?;
+            (old, new)
+        }
+
+        _ => panic!("Invalid update target `{}`", target),
+    };
+
+    Ok(if postfix { old } else { new })
+}
+
+fn call(
+    callee: &Expr,
+    paren: &Token,
+    args: &[Expr],
+    env: &Rc<RefCell<Env>>,
+) -> Result<Value, RuntimeError> {
+    let callee = eval(callee, env)?;
+
+    let mut evaluated = Vec::with_capacity(args.len());
+    for arg in args {
+        evaluated.push(eval(arg, env)?);
+    }
+
+    let callable: &dyn Callable = match &callee {
+        Value::Function(fun) => fun.as_ref(),
+        Value::Class(class) => class,
+        Value::Native(native) => native.as_ref(),
+        _ => return Err(not_callable(callee, paren.span)),
+    };
+
+    if evaluated.len() != callable.arity() {
+        return Err(arity_mismatch(
+            callable.arity(),
+            evaluated.len(),
+            paren.span,
+        ));
+    }
+
+    callable
+        .call(evaluated, paren.span)
+        .map_err(|err| err.with_frame(callable.name().into(), paren.span))
+}
+
+fn map(
+    brace: &Token,
+    entries: &[(Expr, Expr)],
+    env: &Rc<RefCell<Env>>,
+) -> Result<Value, RuntimeError> {
+    let mut evaluated = Vec::with_capacity(entries.len());
+    for (key, value) in entries {
+        evaluated.push((eval(key, env)?, eval(value, env)?));
+    }
+
+    // Keys are only checked once every entry is evaluated, like the VM does.
+    let mut map = HashMap::with_capacity(entries.len());
+    for (key, value) in evaluated {
+        map.insert(ops::map_key(&key, brace.span)?, value);
+    }
+
+    Ok(Value::from(map))
+}
+
+// Looks up `method` on the superclass bound at `local`, bound to the current `this`.
+fn super_method(
+    method: &Token,
+    local: Local,
+    env: &Rc<RefCell<Env>>,
+) -> Result<Value, RuntimeError> {
+    let superclass = match Env::get_local(env, local) {
+        Value::Class(class) => class,
+        got => panic!("`super` should always be bound to a class, got `{:?}`", got),
+    };
+
+    // The environment binding `this` is always right inside the one binding `super`,
+    // and holds nothing else.
+    let this = Local {
+        depth: local.depth - 1,
+        slot: 0,
+    };
+    let instance = match Env::get_local(env, this) {
+        Value::Instance(instance) => instance,
+        got => panic!(
+            "`this` should always be bound to an instance, got `{:?}`",
+            got
+        ),
+    };
+
+    match superclass.find_method(&method.lexeme) {
+        Some(found) => Ok(Value::Function(Rc::new(found.bind(&instance)))),
+        None => Err(undefined_super_method(
+            superclass.name().into(),
+            method.clone(),
+        )),
     }
 }
@@ -1,11 +1,48 @@
-use std::{cell::RefCell, rc::Rc};
+use std::{cell::RefCell, collections::HashMap, rc::Rc};
 
-use super::{env::Env, eval::eval, runtime_error::RuntimeError};
-use crate::{parser::stmt::Stmt, scanner::{literal::Literal, token::Token, token_kind::TokenKind}};
+use super::{
+    class::{Class, INITIALIZER},
+    env::Env,
+    eval::eval,
+    function::{super_token, Function},
+    runtime_error::{superclass_not_class, thrown, RuntimeError},
+    value::Value,
+};
+use crate::{
+    parser::{
+        expr::Expr,
+        stmt::{Block, FunctionDecl, Stmt},
+    },
+    scanner::{token::Token, token_kind::TokenKind},
+};
+
+/// How control leaves a statement after executing it.
+#[derive(Debug)]
+pub enum Flow<'a> {
+    /// Carry on with the next statement.
+    Normal,
+    /// Unwind up to the enclosing function call, which evaluates to the value.
+    Return(Value),
+    /// Leave the innermost loop, or the one with the given label.
+    Break(Option<&'a str>),
+    /// Skip to the next iteration of the innermost loop, or the one with the given label.
+    Continue(Option<&'a str>),
+}
+
+impl Flow<'_> {
+    // Returns true if a `break` or `continue` with `jump_label` refers to the loop labeled `label`.
+    fn targets(jump_label: Option<&str>, label: &Option<Token>) -> bool {
+        match (jump_label, label) {
+            (None, _) => true,
+            (Some(jump_label), Some(label)) => jump_label == label.lexeme,
+            (Some(_), None) => false,
+        }
+    }
+}
 
 /// Executes a single statament tree, possibly causing side effects.
 /// This is the statement analogue of `eval`.
-pub fn execute(stmt: Stmt, env: Rc<RefCell<Env>>) -> Result<(), RuntimeError> {
+pub fn execute<'a>(stmt: &'a Stmt, env: &Rc<RefCell<Env>>) -> Result<Flow<'a>, RuntimeError> {
     match stmt {
         Stmt::Expr(expr) => {
             eval(expr, env)?;
@@ -14,32 +51,212 @@ pub fn execute(stmt: Stmt, env: Rc<RefCell<Env>>) -> Result<(), RuntimeError> {
         Stmt::Print(expr) => println!("{}", eval(expr, env)?),
 
         Stmt::Var { name, init } => {
-            let value = eval(init, env.clone())?;
+            let value = eval(init, env)?;
             env.borrow_mut().define(name, value);
         }
 
-        Stmt::Block(stmts) => {
-            let new_env = Env::new_enclosed(&env);
-
-            for stmt in stmts {
-                execute(stmt, new_env.clone())?;
-            }
-        },
+        Stmt::Block(block) => return execute_block(block, None, env),
 
         Stmt::If { condition, then_branch, else_branch } => {
-            if eval(condition, env.clone())?.is_truthy() {
-                execute(*then_branch, env)?;
+            if eval(condition, env)?.is_truthy() {
+                return execute(then_branch, env);
             } else if let Some(else_branch) = else_branch {
-                execute(*else_branch, env)?;
+                return execute(else_branch, env);
             }
         }
 
-        Stmt::While { condition, body } => {
-            while eval(condition.clone(), env.clone())?.is_truthy() {
-                execute(*body.clone(), env.clone())?;
+        Stmt::While {
+            condition,
+            body,
+            increment,
+            label,
+        } => return while_loop(condition, body, increment.as_ref(), label, env),
+
+        Stmt::Break { label, .. } => {
+            return Ok(Flow::Break(label.as_ref().map(|l| l.lexeme.as_str())))
+        }
+
+        Stmt::Continue { label, .. } => {
+            return Ok(Flow::Continue(label.as_ref().map(|l| l.lexeme.as_str())))
+        }
+
+        Stmt::Function(decl) => {
+            let fun = Function::new(Rc::clone(decl), Rc::clone(env), false);
+            env.borrow_mut()
+                .define(&decl.name, Value::Function(Rc::new(fun)));
+        }
+
+        Stmt::Return { keyword, value } => {
+            let value = match value {
+                Some(expr) => eval(expr, env)?,
+                None => Value::Nil,
+            };
+
+            return Ok(Flow::Return(value));
+        }
+
+        Stmt::Class {
+            name,
+            superclass,
+            methods,
+        } => class(name, superclass.as_ref(), methods, env)?,
+
+        Stmt::Try {
+            body,
+            catch,
+            finally,
+            ..
+        } => return try_catch(body, catch.as_ref(), finally.as_ref(), env),
+
+        // Rethrowing a caught error raises it again as it was.
+        Stmt::Throw { keyword, value } => {
+            return match eval(value, env)? {
+                Value::Error(err) => Err(err.as_ref().clone()),
+                value => Err(thrown(value, keyword.span)),
             }
         }
     };
 
+    Ok(Flow::Normal)
+}
+
+// Executes statements in order inside `env`, stopping at the first one that leaves early.
+fn execute_all<'a>(stmts: &'a [Stmt], env: &Rc<RefCell<Env>>) -> Result<Flow<'a>, RuntimeError> {
+    for stmt in stmts {
+        match execute(stmt, env)? {
+            Flow::Normal => (),
+            flow => return Ok(flow),
+        }
+    }
+
+    Ok(Flow::Normal)
+}
+
+// Executes a block, with `binding` defined as its first variable.
+// Unless the block has an environment of its own, its variables are defined in `env` and dropped
+// from it again however the block is left.
+fn execute_block<'a>(
+    block: &'a Block,
+    binding: Option<(&Token, Value)>,
+    env: &Rc<RefCell<Env>>,
+) -> Result<Flow<'a>, RuntimeError> {
+    let (env, count) = if block.own_env {
+        (Env::new_enclosed(env), None)
+    } else {
+        (Rc::clone(env), Some(env.borrow().local_count()))
+    };
+
+    if let Some((name, value)) = binding {
+        env.borrow_mut().define(name, value);
+    }
+
+    let result = execute_all(&block.stmts, &env);
+
+    if let Some(count) = count {
+        env.borrow_mut().truncate_locals(count);
+    }
+
+    result
+}
+
+// Like in `eval`, the bigger cases get their own functions to keep the native stack taken
+// by each level of `execute` small.
+
+fn while_loop<'a>(
+    condition: &Expr,
+    body: &'a Stmt,
+    increment: Option<&Expr>,
+    label: &Option<Token>,
+    env: &Rc<RefCell<Env>>,
+) -> Result<Flow<'a>, RuntimeError> {
+    while eval(condition, env)?.is_truthy() {
+        match execute(body, env)? {
+            Flow::Normal => (),
+            Flow::Break(jump_label) if Flow::targets(jump_label, label) => break,
+            Flow::Continue(jump_label) if Flow::targets(jump_label, label) => (),
+            // Returns and jumps to outer loops keep unwinding.
+            flow => return Ok(flow),
+        }
+
+        if let Some(increment) = increment {
+            eval(increment, env)?;
+        }
+    }
+
+    Ok(Flow::Normal)
+}
+
+fn class(
+    name: &Token,
+    superclass: Option<&Expr>,
+    methods: &[Rc<FunctionDecl>],
+    env: &Rc<RefCell<Env>>,
+) -> Result<(), RuntimeError> {
+    let superclass = match superclass {
+        Some(expr) => {
+            let Expr::Variable {
+                name: super_name, ..
+            } = expr
+            else {
+                panic!("Superclass should always be a variable expression");
+            };
+
+            match eval(expr, env)? {
+                Value::Class(class) => Some(class),
+                got => return Err(superclass_not_class(super_name.clone(), got)),
+            }
+        }
+        None => None,
+    };
+
+    // Methods of a subclass close over an extra scope where `super` is bound,
+    // so that `super.method()` can find it no matter which instance calls it.
+    let method_env = match &superclass {
+        Some(superclass) => {
+            let super_env = Env::new_enclosed(env);
+            super_env
+                .borrow_mut()
+                .define(&super_token(name.span), Value::Class(Rc::clone(superclass)));
+            super_env
+        }
+        None => Rc::clone(env),
+    };
+
+    let methods: HashMap<String, Rc<Function>> = methods
+        .iter()
+        .map(|decl| {
+            let is_initializer = decl.name.lexeme == INITIALIZER;
+            let method = Function::new(Rc::clone(decl), Rc::clone(&method_env), is_initializer);
+            (decl.name.lexeme.clone(), Rc::new(method))
+        })
+        .collect();
+
+    let class = Class::new(name.lexeme.clone(), superclass, methods);
+    env.borrow_mut().define(name, Value::Class(Rc::new(class)));
+
     Ok(())
 }
+
+fn try_catch<'a>(
+    body: &'a Block,
+    catch: Option<&'a (Token, Block)>,
+    finally: Option<&'a Block>,
+    env: &Rc<RefCell<Env>>,
+) -> Result<Flow<'a>, RuntimeError> {
+    let mut result = execute_block(body, None, env);
+
+    if let (Err(err), Some((name, catch))) = (&result, catch) {
+        let err = Value::Error(Rc::new(err.clone()));
+        result = execute_block(catch, Some((name, err)), env);
+    }
+
+    // Leaving the finally block early overrides however the other blocks were left.
+    if let Some(finally) = finally {
+        match execute_block(finally, None, env)? {
+            Flow::Normal => (),
+            flow => return Ok(flow),
+        }
+    }
+
+    result
+}
@@ -0,0 +1,206 @@
+use std::{
+    cell::{Cell, RefCell},
+    fmt,
+    rc::Rc,
+};
+
+use super::{
+    callable::Callable,
+    class::Instance,
+    env::Env,
+    execute::{execute, Flow},
+    runtime_error::{stack_overflow, RuntimeError},
+    value::Value,
+};
+use crate::{
+    parser::{expr::Local, stmt::FunctionDecl},
+    scanner::{span::Span, token::Token, token_kind::TokenKind as TK},
+    vm::chunk::Proto,
+};
+
+/// How many calls can be active at once, the script included, before either backend gives up.
+/// The tree-walker recurses on the native stack for every call, so this is kept low enough
+/// for it to fit in a 2 MiB thread stack, even in debug builds.
+pub const MAX_FRAMES: usize = 1 << 7;
+
+thread_local! {
+    // How many tree-walking calls are running. Each one recurses on the native stack, so
+    // unbounded recursion in a script would otherwise overflow it and abort the process.
+    static DEPTH: Cell<usize> = const { Cell::new(0) };
+}
+
+/// A compiled function together with the variables it captured.
+#[derive(Debug)]
+pub struct Closure {
+    pub proto: Rc<Proto>,
+    pub upvalues: Vec<Rc<RefCell<Upvalue>>>,
+}
+
+/// A captured variable. It stays on the VM's stack for as long as the function declaring it
+/// is running, and is moved into the upvalue itself afterwards.
+#[derive(Debug)]
+pub enum Upvalue {
+    Open(usize),
+    Closed(Value),
+}
+
+/// A user-defined function, runnable by whichever backend created it.
+pub struct Function {
+    body: Body,
+    is_initializer: bool,
+}
+
+pub enum Body {
+    /// Executes the declaration's syntax tree, inside the environment it was declared in.
+    Tree {
+        decl: Rc<FunctionDecl>,
+        closure: Rc<RefCell<Env>>,
+    },
+    /// Runs on the VM. Bound methods carry the instance that becomes their `this`.
+    Compiled {
+        closure: Rc<Closure>,
+        receiver: Option<Rc<RefCell<Instance>>>,
+    },
+}
+
+impl Function {
+    pub fn new(decl: Rc<FunctionDecl>, closure: Rc<RefCell<Env>>, is_initializer: bool) -> Self {
+        Self {
+            body: Body::Tree { decl, closure },
+            is_initializer,
+        }
+    }
+
+    pub fn compiled(closure: Rc<Closure>, is_initializer: bool) -> Self {
+        Self {
+            body: Body::Compiled {
+                closure,
+                receiver: None,
+            },
+            is_initializer,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        match &self.body {
+            Body::Tree { decl, .. } => &decl.name.lexeme,
+            Body::Compiled { closure, .. } => &closure.proto.name,
+        }
+    }
+
+    pub fn body(&self) -> &Body {
+        &self.body
+    }
+
+    // Executes the body of a tree-walking function with its arguments bound.
+    fn run(&self, args: Vec<Value>) -> Result<Value, RuntimeError> {
+        let Body::Tree { decl, closure } = &self.body else {
+            unreachable!("Compiled functions are only ever called by the VM");
+        };
+
+        // Each call gets its own environment, so recursion and closures created
+        // by different calls don't step on each other.
+        let env = Env::new_enclosed(closure);
+
+        for (param, arg) in decl.params.iter().zip(args) {
+            env.borrow_mut().define(param, arg);
+        }
+
+        for stmt in &decl.body {
+            if let Flow::Return(value) = execute(stmt, &env)? {
+                if self.is_initializer {
+                    break;
+                }
+
+                return Ok(value);
+            }
+        }
+
+        // Initializers always hand back the instance, even when called directly or
+        // left early through an empty `return`.
+        if self.is_initializer {
+            // Bound methods close over a scope holding only `this`.
+            let this = Local { depth: 0, slot: 0 };
+            return Ok(Env::get_local(closure, this));
+        }
+
+        Ok(Value::Nil)
+    }
+
+    /// Returns a copy of the method with `this` bound to `instance`.
+    pub fn bind(&self, instance: &Rc<RefCell<Instance>>) -> Function {
+        let body = match &self.body {
+            Body::Tree { decl, closure } => {
+                let env = Env::new_enclosed(closure);
+                env.borrow_mut().define(
+                    &this_token(decl.name.span),
+                    Value::Instance(Rc::clone(instance)),
+                );
+
+                Body::Tree {
+                    decl: Rc::clone(decl),
+                    closure: env,
+                }
+            }
+
+            Body::Compiled { closure, .. } => Body::Compiled {
+                closure: Rc::clone(closure),
+                receiver: Some(Rc::clone(instance)),
+            },
+        };
+
+        Function {
+            body,
+            is_initializer: self.is_initializer,
+        }
+    }
+}
+
+/// Returns a token that can be used to look up `this` in an environment.
+pub fn this_token(span: Span) -> Token {
+    Token::symbol(TK::This, "this".into(), span)
+}
+
+/// Returns a token that can be used to look up `super` in an environment.
+pub fn super_token(span: Span) -> Token {
+    Token::symbol(TK::Super, "super".into(), span)
+}
+
+impl Callable for Function {
+    fn name(&self) -> &str {
+        Function::name(self)
+    }
+
+    fn arity(&self) -> usize {
+        match &self.body {
+            Body::Tree { decl, .. } => decl.params.len(),
+            Body::Compiled { closure, .. } => closure.proto.arity,
+        }
+    }
+
+    fn call(&self, args: Vec<Value>, span: Span) -> Result<Value, RuntimeError> {
+        // Gives up at the same depth as the VM, which counts the script as a frame.
+        let depth = DEPTH.get();
+        if depth + 1 >= MAX_FRAMES {
+            return Err(stack_overflow(span));
+        }
+
+        DEPTH.set(depth + 1);
+        let result = self.run(args);
+        DEPTH.set(depth);
+
+        result
+    }
+}
+
+impl fmt::Debug for Function {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "<fn {}>", self.name())
+    }
+}
+
+impl fmt::Display for Function {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self, f)
+    }
+}
@@ -0,0 +1,111 @@
+use std::{
+    cmp::Ordering,
+    fmt,
+    hash::{Hash, Hasher},
+};
+
+use super::value::Value;
+
+/// A value that can be used as a map key.
+/// Only values compared by contents qualify, since objects are compared by identity and
+/// could be mutated while in a map.
+#[derive(Clone, Debug)]
+pub enum Key {
+    Nil,
+    Bool(bool),
+    Int(i64),
+    Number(f64),
+    String(String),
+}
+
+impl Key {
+    /// Returns the key `value` stands for, or `None` if it can't be one.
+    pub fn new(value: &Value) -> Option<Key> {
+        match value {
+            Value::Nil => Some(Key::Nil),
+            Value::Bool(b) => Some(Key::Bool(*b)),
+            Value::Int(n) => Some(Key::Int(*n)),
+            // Numbers that are equal must be the same key, so 1.0 is the key 1, and -0.0 is 0.
+            Value::Number(n)
+                if n.fract() == 0. && (i64::MIN as f64..i64::MAX as f64).contains(n) =>
+            {
+                Some(Key::Int(*n as i64))
+            }
+            Value::Number(n) => Some(Key::Number(*n)),
+            Value::String(s) => Some(Key::String(s.clone())),
+            _ => None,
+        }
+    }
+
+    // Keys of different types are ordered by type first, for `cmp`.
+    fn rank(&self) -> u8 {
+        match self {
+            Key::Nil => 0,
+            Key::Bool(_) => 1,
+            Key::Int(_) | Key::Number(_) => 2,
+            Key::String(_) => 3,
+        }
+    }
+}
+
+// Numbers are compared bitwise rather than with `==`, so that NaN can still be looked up.
+impl PartialEq for Key {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Key {}
+
+impl Hash for Key {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.rank().hash(state);
+        match self {
+            Key::Nil => (),
+            Key::Bool(b) => b.hash(state),
+            Key::Int(n) => n.hash(state),
+            Key::Number(n) => n.to_bits().hash(state),
+            Key::String(s) => s.hash(state),
+        }
+    }
+}
+
+// Maps have no order of their own, this one is used to list keys predictably.
+impl Ord for Key {
+    fn cmp(&self, other: &Self) -> Ordering {
+        match (self, other) {
+            (Key::Bool(l), Key::Bool(r)) => l.cmp(r),
+            (Key::Int(l), Key::Int(r)) => l.cmp(r),
+            (Key::Number(l), Key::Number(r)) => l.total_cmp(r),
+            // An integer never equals a number key, even if they are as close as floats get.
+            (Key::Int(l), Key::Number(r)) => (*l as f64).total_cmp(r).then(Ordering::Less),
+            (Key::Number(l), Key::Int(r)) => l.total_cmp(&(*r as f64)).then(Ordering::Greater),
+            (Key::String(l), Key::String(r)) => l.cmp(r),
+            _ => self.rank().cmp(&other.rank()),
+        }
+    }
+}
+
+impl PartialOrd for Key {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl From<&Key> for Value {
+    fn from(key: &Key) -> Self {
+        match key {
+            Key::Nil => Value::Nil,
+            Key::Bool(b) => Value::Bool(*b),
+            Key::Int(n) => Value::Int(*n),
+            Key::Number(n) => Value::Number(*n),
+            Key::String(s) => Value::String(s.clone()),
+        }
+    }
+}
+
+impl fmt::Display for Key {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", Value::from(self))
+    }
+}
@@ -1,12 +1,28 @@
+pub mod callable;
+pub mod class;
 mod env;
 mod eval;
 mod execute;
+pub mod ops;
+pub mod function;
+pub mod map;
+pub mod native;
 pub mod runtime_error;
+pub mod value;
 
 use std::{cell::RefCell, rc::Rc};
 
-use self::{env::Env, execute::execute, runtime_error::RuntimeError};
-use crate::parser::stmt::Stmt;
+use self::{
+    env::Env,
+    execute::execute,
+    native::{NativeFunction, BUILTINS},
+    runtime_error::RuntimeError,
+    value::Value,
+};
+use crate::{
+    parser::stmt::Stmt,
+    scanner::{span::Span, token::Token, token_kind::TokenKind as TK},
+};
 
 pub struct Interpreter {
     env: Rc<RefCell<Env>>,
@@ -14,17 +30,482 @@ pub struct Interpreter {
 
 impl Default for Interpreter {
     fn default() -> Self {
-        Self { env: Env::new() }
+        let mut interpreter = Self { env: Env::new() };
+        for &(name, arity, fun) in BUILTINS {
+            interpreter.define_native(name, arity, fun);
+        }
+        interpreter
     }
 }
 
 impl Interpreter {
+    /// Exposes a host function to scripts as a global called `name`, overwriting any
+    /// previous global with that name.
+    /// `fun` is only ever called with exactly `arity` arguments. An `Err` it returns
+    /// becomes a runtime error at the call site.
+    pub fn define_native(
+        &mut self,
+        name: &str,
+        arity: usize,
+        fun: impl Fn(Vec<Value>) -> Result<Value, String> + 'static,
+    ) {
+        let native = NativeFunction::new(name.into(), arity, Rc::new(fun));
+        // Natives have no location in the source.
+        let name = Token::symbol(TK::Identifier, name.into(), Span::default());
+
+        self.env
+            .borrow_mut()
+            .define(&name, Value::Native(Rc::new(native)));
+    }
+
     /// Executes multiple stataments, possibly causing side effects.
-    pub fn interpret(&mut self, stmts: Vec<Stmt>) -> Result<(), RuntimeError> {
+    /// The program is only borrowed, so it can be run again afterwards.
+    pub fn interpret(&mut self, stmts: &[Stmt]) -> Result<(), RuntimeError> {
         for stmt in stmts {
-            execute(stmt, self.env.clone())?;
+            execute(stmt, &self.env)?;
         }
 
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, rc::Rc};
+
+    use super::{function::MAX_FRAMES, value::Value, Interpreter};
+    use crate::{
+        parser::parse,
+        resolver::resolve,
+        scanner::{span::Span, token::Token, token_kind::TokenKind as TK, tokenize},
+    };
+
+    fn run(source: &str) -> Interpreter {
+        run_with(Interpreter::default(), source)
+    }
+
+    fn run_with(mut interpreter: Interpreter, source: &str) -> Interpreter {
+        let mut stmts = parse(tokenize(source.into()).unwrap()).unwrap();
+        resolve(&mut stmts).unwrap();
+        interpreter.interpret(&stmts).unwrap();
+        interpreter
+    }
+
+    fn global(interpreter: &Interpreter, name: &str) -> Value {
+        let name = Token::symbol(TK::Identifier, name.into(), Span::default());
+        interpreter.env.borrow().get(&name).unwrap()
+    }
+
+    #[test]
+    fn test_closures() {
+        let interpreter = run("
+            fun makeCounter() {
+                var i = 0;
+                fun count() {
+                    i = i + 1;
+                    return i;
+                }
+                return count;
+            }
+
+            var counter = makeCounter();
+            counter();
+            var result = counter();
+        ");
+
+        assert_eq!(global(&interpreter, "result"), Value::Int(2));
+    }
+
+    #[test]
+    fn test_classes() {
+        let interpreter = run("
+            class Point {
+                init(x, y) {
+                    this.x = x;
+                    this.y = y;
+                }
+
+                sum() {
+                    return this.x + this.y;
+                }
+            }
+
+            var point = Point(1, 2);
+            point.x = 10;
+            var sum = point.sum;
+            var result = sum();
+        ");
+
+        assert_eq!(global(&interpreter, "result"), Value::Int(12));
+    }
+
+    #[test]
+    fn test_static_scope() {
+        // Without static resolution, the second call would see the block's `a`.
+        let interpreter = run("
+            var a = \"global\";
+            var first;
+            var second;
+            {
+                fun show() {
+                    return a;
+                }
+
+                first = show();
+                var a = \"block\";
+                second = show();
+            }
+        ");
+
+        assert_eq!(global(&interpreter, "first"), Value::from("global"));
+        assert_eq!(global(&interpreter, "second"), Value::from("global"));
+    }
+
+    #[test]
+    fn test_block_slots() {
+        let interpreter = run("
+            var shadowed;
+            {
+                var a = 1;
+                {
+                    var b = a;
+                    var a = 2;
+                    {
+                        var a = 20;
+                        shadowed = a + b;
+                    }
+                    shadowed = shadowed + a;
+                }
+                shadowed = shadowed + a;
+            }
+
+            // Every pass through the body gets its own `j` to capture.
+            var first;
+            var last;
+            for (var i = 0; i < 3; i = i + 1) {
+                var j = i;
+                fun get() { return j; }
+                if (i == 0) first = get;
+                last = get;
+            }
+            var captured = first() * 10 + last();
+
+            var later;
+            {
+                var a = \"a\";
+                fun show() { return a; }
+                var b = \"b\";
+                a = a + b;
+                later = show();
+            }
+        ");
+
+        assert_eq!(global(&interpreter, "shadowed"), Value::Int(24));
+        assert_eq!(global(&interpreter, "captured"), Value::Int(2));
+        assert_eq!(global(&interpreter, "later"), Value::from("ab"));
+    }
+
+    #[test]
+    fn test_blocks_in_functions() {
+        // Blocks in a function keep their variables in the call's environment, so their slots
+        // must be handed back however they are left, for the variables declared after them.
+        let interpreter = run("
+            fun f(a) {
+                {
+                    var b = a + 1;
+                    {
+                        var a = b * 10;
+                        b = a + b;
+                    }
+                    a = a + b;
+                }
+
+                for (var i = 0; i < 3; i = i + 1) {
+                    var skipped = i;
+                    if (i == 1) continue;
+                    var c = skipped * 100;
+                    a = a + c;
+                }
+
+                try {
+                    var d = 1000;
+                    throw d;
+                } catch (e) {
+                    var d = 2000;
+                    a = a + d;
+                }
+
+                var last = a;
+                return last;
+            }
+            var result = f(1);
+        ");
+
+        assert_eq!(global(&interpreter, "result"), Value::Int(2223));
+    }
+
+    #[test]
+    fn test_inheritance() {
+        let interpreter = run("
+            class A {
+                name() {
+                    return \"A\";
+                }
+            }
+
+            class B < A {
+                name() {
+                    return \"B\" + super.name();
+                }
+            }
+
+            class C < B {}
+
+            var result = C().name();
+        ");
+
+        assert_eq!(global(&interpreter, "result"), Value::from("BA"));
+    }
+
+    #[test]
+    fn test_object_identity() {
+        let interpreter = run("
+            class A {}
+            var a = A();
+            var same = a == a;
+            var different = a == A();
+        ");
+
+        assert_eq!(global(&interpreter, "same"), Value::Bool(true));
+        assert_eq!(global(&interpreter, "different"), Value::Bool(false));
+    }
+
+    #[test]
+    fn test_natives() {
+        let logged = Rc::new(RefCell::new(Vec::new()));
+        let mut interpreter = Interpreter::default();
+
+        let log = Rc::clone(&logged);
+        interpreter.define_native("log", 1, move |args| {
+            log.borrow_mut().push(args[0].to_string());
+            Ok(Value::Nil)
+        });
+        interpreter.define_native("fail", 0, |_| Err("on purpose".into()));
+
+        let mut interpreter = run_with(interpreter, "log(\"a\" + 1); var time = clock();");
+
+        assert_eq!(*logged.borrow(), vec!["a1".to_string()]);
+        assert!(matches!(global(&interpreter, "time"), Value::Number(_)));
+
+        let mut stmts = parse(tokenize("fail();".into()).unwrap()).unwrap();
+        resolve(&mut stmts).unwrap();
+        assert!(interpreter.interpret(&stmts).is_err());
+    }
+
+    #[test]
+    fn test_numbers() {
+        let interpreter = run("
+            var big = 9007199254740993 + 1;
+            var divided = 7 / -2;
+            var exact = float(7) / -2;
+            var shown = \"\" + 1.0 + \" \" + 2 ** -1 + \" \" + 4 / 2;
+            var mixed = 1 + 0.5;
+            var same = 2 == 2.0 and 1 < 1.5;
+            var converted = int(-2.9) + int(\"40\") + float(1);
+        ");
+
+        assert_eq!(global(&interpreter, "big"), Value::Int(9007199254740994));
+        assert_eq!(global(&interpreter, "divided"), Value::Int(-3));
+        assert_eq!(global(&interpreter, "exact"), Value::Number(-3.5));
+        assert_eq!(global(&interpreter, "shown"), Value::from("1.0 0.5 2"));
+        assert_eq!(global(&interpreter, "mixed"), Value::Number(1.5));
+        assert_eq!(global(&interpreter, "same"), Value::Bool(true));
+        assert_eq!(global(&interpreter, "converted"), Value::Number(39.0));
+
+        for (source, op) in [
+            ("9223372036854775807 + 1;", "Plus"),
+            ("(-9223372036854775807 - 1) / -1;", "Slash"),
+        ] {
+            let mut stmts = parse(tokenize(source.into()).unwrap()).unwrap();
+            resolve(&mut stmts).unwrap();
+            let error = Interpreter::default().interpret(&stmts).unwrap_err();
+            assert_eq!(error.message(), format!("Integer overflow in `{}`", op));
+        }
+    }
+
+    #[test]
+    fn test_lists() {
+        let interpreter = run("
+            var xs = [1, 2, [3]];
+            xs[0] = xs[-1];
+            push(xs, 4);
+            insert(xs, 1, 0);
+            var popped = pop(xs);
+            var result = \"\" + xs + len(xs);
+            var aliased = xs[0] == xs[-1];
+        ");
+
+        assert_eq!(global(&interpreter, "result"), Value::from("[[3], 0, 2, [3]]4"));
+        assert_eq!(global(&interpreter, "popped"), Value::Int(4));
+        assert_eq!(global(&interpreter, "aliased"), Value::Bool(true));
+    }
+
+    #[test]
+    fn test_cyclic_lists() {
+        let mut interpreter = run("
+            var a = [1];
+            push(a, a);
+            var shown = \"\" + [a];
+        ");
+
+        assert_eq!(global(&interpreter, "shown"), Value::from("[[1, [...]]]"));
+
+        let mut stmts = parse(tokenize("int(a);".into()).unwrap()).unwrap();
+        resolve(&mut stmts).unwrap();
+        let error = interpreter.interpret(&stmts).unwrap_err();
+        assert_eq!(
+            error.message(),
+            "Native function `int` failed: Expected a number or a string, \
+             got `List(RefCell { value: [Int(1), [...]] })`"
+        );
+    }
+
+    #[test]
+    fn test_maps() {
+        let interpreter = run("
+            var m = {\"a\": 1, 2: \"two\"};
+            m[\"b\"] = m[\"a\"] + 1;
+            m[-0] = nil;
+            var removed = remove(m, 2);
+            var result = \"\" + keys(m) + len(m) + has(m, 0) + has(m, \"c\");
+        ");
+
+        assert_eq!(global(&interpreter, "result"), Value::from("[0, a, b]3truefalse"));
+        assert_eq!(global(&interpreter, "removed"), Value::from("two"));
+    }
+
+    #[test]
+    fn test_cyclic_maps() {
+        let mut interpreter = run("
+            var m = {\"a\": 1};
+            m[\"self\"] = m;
+            var n = {};
+            n[\"xs\"] = [n];
+            var shown = \"\" + m + \" \" + n;
+        ");
+
+        assert_eq!(
+            global(&interpreter, "shown"),
+            Value::from("{a: 1, self: {...}} {xs: [{...}]}")
+        );
+
+        let mut stmts = parse(tokenize("int(n);".into()).unwrap()).unwrap();
+        resolve(&mut stmts).unwrap();
+        let error = interpreter.interpret(&stmts).unwrap_err();
+        assert_eq!(
+            error.message(),
+            "Native function `int` failed: Expected a number or a string, \
+             got `Map(RefCell { value: {String(\"xs\"): List(RefCell { value: [{...}] })} })`"
+        );
+    }
+
+    #[test]
+    fn test_lambdas() {
+        let interpreter = run("
+            fun twice(f, x) { return f(f(x)); }
+            var offset = 10;
+            var shifted = twice((x) => x + offset, 1);
+            var doubled = twice(fun (x) { return x * 2; }, 3);
+        ");
+
+        assert_eq!(global(&interpreter, "shifted"), Value::Int(21));
+        assert_eq!(global(&interpreter, "doubled"), Value::Int(12));
+    }
+
+    #[test]
+    fn test_exceptions() {
+        let interpreter = run("
+            var log = \"\";
+            fun risky(n) {
+                try {
+                    if (n == 0) return 1 / n;
+                    throw n;
+                } finally {
+                    log = log + n;
+                }
+            }
+
+            for (var i = 0; i < 2; i++) {
+                try {
+                    risky(i);
+                } catch (e) {
+                    log = log + \" \" + e.value + \":\" + e.line + \" \";
+                }
+            }
+            var message;
+            try { risky(0); } catch (e) { message = e.message; }
+        ");
+
+        assert_eq!(global(&interpreter, "log"), Value::from("0 Nil:5 1 1:6 0"));
+        assert_eq!(
+            global(&interpreter, "message"),
+            Value::from("Cannot divide `Int(1)` by zero")
+        );
+    }
+
+    #[test]
+    fn test_stack_overflow() {
+        let mut interpreter = run("
+            fun forever() { forever(); }
+            fun countdown(n) { if (n > 0) return countdown(n - 1); return n; }
+        ");
+
+        let mut stmts = parse(tokenize("forever();".into()).unwrap()).unwrap();
+        resolve(&mut stmts).unwrap();
+        let error = interpreter.interpret(&stmts).unwrap_err();
+        assert_eq!(error.message(), "Stack overflow");
+
+        // Running again starts from an empty call stack, so the deepest call allowed works.
+        let source = format!("var result = countdown({});", MAX_FRAMES - 2);
+        let interpreter = run_with(interpreter, &source);
+        assert_eq!(global(&interpreter, "result"), Value::Int(0));
+    }
+
+    #[test]
+    fn test_rerun() {
+        let mut interpreter = run("var runs = 0;");
+
+        let mut stmts = parse(tokenize("runs = runs + 1;".into()).unwrap()).unwrap();
+        resolve(&mut stmts).unwrap();
+        interpreter.interpret(&stmts).unwrap();
+        interpreter.interpret(&stmts).unwrap();
+
+        assert_eq!(global(&interpreter, "runs"), Value::Int(2));
+    }
+
+    #[test]
+    fn test_traceback() {
+        let source = "
+            fun inner() {
+                return 1 / 0;
+            }
+
+            fun outer() {
+                inner();
+            }
+
+            outer();
+        ";
+
+        let mut stmts = parse(tokenize(source.into()).unwrap()).unwrap();
+        resolve(&mut stmts).unwrap();
+        let err = Interpreter::default().interpret(&stmts).unwrap_err();
+
+        let frames: Vec<(&str, usize)> = err
+            .trace
+            .iter()
+            .map(|frame| (frame.function.as_str(), frame.call_site.line))
+            .collect();
+        assert_eq!(frames, vec![("inner", 7), ("outer", 10)]);
+    }
+}
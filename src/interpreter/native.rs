@@ -0,0 +1,208 @@
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    fmt,
+    rc::Rc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use super::{
+    callable::Callable,
+    map::Key,
+    ops,
+    runtime_error::{native_failed, RuntimeError, RuntimeErrorKind},
+    value::Value,
+};
+use crate::scanner::span::Span;
+
+/// The Rust side of a native function.
+/// Returning `Err` raises a runtime error carrying the message.
+pub type NativeFn = dyn Fn(Vec<Value>) -> Result<Value, String>;
+
+/// A native that needs no state of its own.
+pub type Builtin = fn(Vec<Value>) -> Result<Value, String>;
+
+/// The natives every backend defines as globals from the start, as (name, arity, function).
+pub const BUILTINS: &[(&str, usize, Builtin)] = &[
+    ("clock", 0, clock),
+    ("len", 1, len),
+    ("push", 2, push),
+    ("pop", 1, pop),
+    ("insert", 3, insert),
+    ("has", 2, has),
+    ("remove", 2, remove),
+    ("keys", 1, keys),
+    ("int", 1, int),
+    ("float", 1, float),
+];
+
+/// A function implemented by the host rather than in Lox.
+pub struct NativeFunction {
+    name: String,
+    arity: usize,
+    fun: Rc<NativeFn>,
+}
+
+impl NativeFunction {
+    pub fn new(name: String, arity: usize, fun: Rc<NativeFn>) -> Self {
+        Self { name, arity, fun }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+impl Callable for NativeFunction {
+    fn name(&self) -> &str {
+        NativeFunction::name(self)
+    }
+
+    fn arity(&self) -> usize {
+        self.arity
+    }
+
+    fn call(&self, args: Vec<Value>, span: Span) -> Result<Value, RuntimeError> {
+        (self.fun)(args).map_err(|message| native_failed(self.name.clone(), message, span))
+    }
+}
+
+impl fmt::Debug for NativeFunction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "<native fn {}>", self.name)
+    }
+}
+
+impl fmt::Display for NativeFunction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self, f)
+    }
+}
+
+/// Returns the amount of seconds since the Unix epoch.
+pub fn clock(_args: Vec<Value>) -> Result<Value, String> {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| Value::Number(elapsed.as_secs_f64()))
+        .map_err(|err| err.to_string())
+}
+
+/// Returns the amount of elements in a list, of entries in a map, or of characters in a string.
+pub fn len(args: Vec<Value>) -> Result<Value, String> {
+    match &args[0] {
+        Value::List(list) => Ok(Value::Int(list.borrow().len() as i64)),
+        Value::Map(map) => Ok(Value::Int(map.borrow().len() as i64)),
+        Value::String(s) => Ok(Value::Int(s.chars().count() as i64)),
+        value => Err(format!(
+            "Expected a list, a map or a string, got `{:?}`",
+            value
+        )),
+    }
+}
+
+/// Appends an element to the end of a list.
+pub fn push(args: Vec<Value>) -> Result<Value, String> {
+    list_arg(&args[0])?.borrow_mut().push(args[1].clone());
+    Ok(Value::Nil)
+}
+
+/// Removes the last element of a list and returns it.
+pub fn pop(args: Vec<Value>) -> Result<Value, String> {
+    list_arg(&args[0])?
+        .borrow_mut()
+        .pop()
+        .ok_or_else(|| "Cannot pop from an empty list".into())
+}
+
+/// Inserts an element so that it ends up at the given index of a list, shifting the ones
+/// after it. Negative indices count from the end, so -1 appends.
+pub fn insert(args: Vec<Value>) -> Result<Value, String> {
+    let mut list = list_arg(&args[0])?.borrow_mut();
+
+    // There is one more place to insert at than there are elements.
+    let position = ops::list_index(&args[1], list.len() + 1, Span::default()).map_err(|err| {
+        match err.kind.as_ref() {
+            RuntimeErrorKind::IndexOutOfBounds { index, .. } => format!(
+                "Cannot insert at index {index} into a list of length {}",
+                list.len()
+            ),
+            _ => err.message(),
+        }
+    })?;
+
+    list.insert(position, args[2].clone());
+    Ok(Value::Nil)
+}
+
+/// Returns whether a map has an entry for a key.
+pub fn has(args: Vec<Value>) -> Result<Value, String> {
+    let key = key_arg(&args[1])?;
+    Ok(Value::Bool(map_arg(&args[0])?.borrow().contains_key(&key)))
+}
+
+/// Removes the entry for a key from a map and returns its value.
+pub fn remove(args: Vec<Value>) -> Result<Value, String> {
+    let key = key_arg(&args[1])?;
+    map_arg(&args[0])?
+        .borrow_mut()
+        .remove(&key)
+        .ok_or_else(|| format!("Key `{:?}` is not in the map", args[1]))
+}
+
+/// Returns a new list of the keys of a map, sorted so that the order is predictable.
+pub fn keys(args: Vec<Value>) -> Result<Value, String> {
+    let mut keys: Vec<Key> = map_arg(&args[0])?.borrow().keys().cloned().collect();
+    keys.sort();
+    Ok(Value::from(
+        keys.iter().map(Value::from).collect::<Vec<_>>(),
+    ))
+}
+
+/// Converts a number, or a string holding one, to an integer. Floats are truncated toward zero.
+pub fn int(args: Vec<Value>) -> Result<Value, String> {
+    match &args[0] {
+        Value::Int(n) => Ok(Value::Int(*n)),
+        Value::Number(n) if (i64::MIN as f64..i64::MAX as f64).contains(&n.trunc()) => {
+            Ok(Value::Int(*n as i64))
+        }
+        Value::Number(n) => Err(format!("`{}` does not fit in an integer", n)),
+        Value::String(s) => s
+            .trim()
+            .parse()
+            .map(Value::Int)
+            .map_err(|_| format!("`{}` is not an integer", s)),
+        value => Err(format!("Expected a number or a string, got `{:?}`", value)),
+    }
+}
+
+/// Converts a number, or a string holding one, to a float.
+pub fn float(args: Vec<Value>) -> Result<Value, String> {
+    match &args[0] {
+        Value::Int(n) => Ok(Value::Number(*n as f64)),
+        Value::Number(n) => Ok(Value::Number(*n)),
+        Value::String(s) => s
+            .trim()
+            .parse()
+            .map(Value::Number)
+            .map_err(|_| format!("`{}` is not a number", s)),
+        value => Err(format!("Expected a number or a string, got `{:?}`", value)),
+    }
+}
+
+fn list_arg(value: &Value) -> Result<&Rc<RefCell<Vec<Value>>>, String> {
+    match value {
+        Value::List(list) => Ok(list),
+        value => Err(format!("Expected a list, got `{:?}`", value)),
+    }
+}
+
+fn map_arg(value: &Value) -> Result<&Rc<RefCell<HashMap<Key, Value>>>, String> {
+    match value {
+        Value::Map(map) => Ok(map),
+        value => Err(format!("Expected a map, got `{:?}`", value)),
+    }
+}
+
+fn key_arg(value: &Value) -> Result<Key, String> {
+    ops::map_key(value, Span::default()).map_err(|err| err.message())
+}
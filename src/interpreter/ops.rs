@@ -0,0 +1,268 @@
+//! The semantics of unary and binary operators, shared by every backend so that they
+//! all agree on results and errors.
+
+use std::cmp::Ordering;
+
+use super::{
+    map::Key,
+    runtime_error::{
+        bad_bin_ops, bad_index, bad_key, bad_un_op, div_by_zero, index_out_of_bounds, missing_key,
+        not_indexable, overflow, RuntimeError,
+    },
+    value::Value,
+};
+use crate::scanner::{span::Span, token_kind::TokenKind as TK};
+
+/// Applies the prefix operator `op` to `r`.
+/// `span` is where the operator appears and is only used for errors.
+pub fn unary(op: TK, r: Value, span: Span) -> Result<Value, RuntimeError> {
+    match op {
+        TK::Minus => match r {
+            Value::Int(n) => n
+                .checked_neg()
+                .map(Value::Int)
+                .ok_or_else(|| overflow(op, span)),
+            Value::Number(n) => Ok(Value::Number(-n)),
+
+            _ => Err(bad_un_op(op, r, span)),
+        },
+
+        TK::Bang => Ok(Value::Bool(!r.is_truthy())),
+
+        TK::Tilde => match r {
+            Value::Int(n) => Ok(Value::Int(!n)),
+            _ => Err(bad_un_op(op, r, span)),
+        },
+
+        _ => panic!("Invalid unary operator `{:?}`", op),
+    }
+}
+
+/// Applies the infix operator `op` to `l` and `r`.
+/// `span` is where the operator appears and is only used for errors.
+pub fn binary(op: TK, l: Value, r: Value, span: Span) -> Result<Value, RuntimeError> {
+    match op {
+        TK::Plus => match (&l, &r) {
+            // "foo" + "bar", "foo" + 1, ...
+            (Value::String(l), r) => Ok(Value::String(format!("{}{}", l, r))),
+
+            // "foo" + "bar", 1 + "bar", ...
+            (l, Value::String(r)) => Ok(Value::String(format!("{}{}", l, r))),
+
+            _ => arithmetic(op, l, r, span, i64::checked_add, |l, r| l + r),
+        },
+
+        TK::Minus => arithmetic(op, l, r, span, i64::checked_sub, |l, r| l - r),
+
+        TK::Star => arithmetic(op, l, r, span, i64::checked_mul, |l, r| l * r),
+
+        // Dividing integers truncates toward zero, like in C. Making either side a float with
+        // `float` gives the exact quotient instead.
+        TK::Slash => {
+            if divides_by_zero(&l, &r) {
+                return Err(div_by_zero(l, span));
+            }
+
+            arithmetic(op, l, r, span, i64::checked_div, |l, r| l / r)
+        }
+
+        // The remainder has the sign of the dividend, like in C.
+        TK::Percent => {
+            if divides_by_zero(&l, &r) {
+                return Err(div_by_zero(l, span));
+            }
+
+            arithmetic(op, l, r, span, i64::checked_rem, |l, r| l % r)
+        }
+
+        TK::StarStar => match numbers(&l, &r) {
+            // Negative powers of integers are fractions, so they are computed as floats.
+            Some(Numbers::Ints(base, exp)) if exp < 0 => {
+                Ok(Value::Number((base as f64).powf(exp as f64)))
+            }
+            _ => arithmetic(op, l, r, span, checked_pow, f64::powf),
+        },
+
+        TK::Ampersand => bitwise(op, l, r, span, |l, r| Some(l & r)),
+        TK::Pipe => bitwise(op, l, r, span, |l, r| Some(l | r)),
+        TK::Caret => bitwise(op, l, r, span, |l, r| Some(l ^ r)),
+        // Shifting by 64 bits or more, or by a negative amount, overflows.
+        TK::LessLess => bitwise(op, l, r, span, |l, r| l.checked_shl(r.try_into().ok()?)),
+        TK::GreaterGreater => bitwise(op, l, r, span, |l, r| l.checked_shr(r.try_into().ok()?)),
+
+        TK::Greater => compare(op, l, r, span, Ordering::is_gt),
+        TK::GreaterEqual => compare(op, l, r, span, Ordering::is_ge),
+        TK::Less => compare(op, l, r, span, Ordering::is_lt),
+        TK::LessEqual => compare(op, l, r, span, Ordering::is_le),
+
+        TK::EqualEqual => equals(op, l, r, span).map(Value::Bool),
+        TK::BangEqual => equals(op, l, r, span).map(|equal| Value::Bool(!equal)),
+
+        _ => panic!("Invalid binary operator `{:?}`", op),
+    }
+}
+
+// The operands of a numeric operator. Integers stay integers, unless they are mixed with
+// floats, in which case both are promoted to floats.
+enum Numbers {
+    Ints(i64, i64),
+    Floats(f64, f64),
+}
+
+fn numbers(l: &Value, r: &Value) -> Option<Numbers> {
+    match (l, r) {
+        (Value::Int(l), Value::Int(r)) => Some(Numbers::Ints(*l, *r)),
+        (Value::Int(l), Value::Number(r)) => Some(Numbers::Floats(*l as f64, *r)),
+        (Value::Number(l), Value::Int(r)) => Some(Numbers::Floats(*l, *r as f64)),
+        (Value::Number(l), Value::Number(r)) => Some(Numbers::Floats(*l, *r)),
+        _ => None,
+    }
+}
+
+// Applies `ints` or `floats` depending on the operands, with `ints` returning `None` on overflow.
+fn arithmetic(
+    op: TK,
+    l: Value,
+    r: Value,
+    span: Span,
+    ints: fn(i64, i64) -> Option<i64>,
+    floats: fn(f64, f64) -> f64,
+) -> Result<Value, RuntimeError> {
+    match numbers(&l, &r) {
+        Some(Numbers::Ints(l, r)) => ints(l, r).map(Value::Int).ok_or_else(|| overflow(op, span)),
+        Some(Numbers::Floats(l, r)) => Ok(Value::Number(floats(l, r))),
+        None => Err(bad_bin_ops(op, l, r, span)),
+    }
+}
+
+fn divides_by_zero(l: &Value, r: &Value) -> bool {
+    match numbers(l, r) {
+        Some(Numbers::Ints(_, r)) => r == 0,
+        Some(Numbers::Floats(_, r)) => r == 0.,
+        None => false,
+    }
+}
+
+fn checked_pow(base: i64, exp: i64) -> Option<i64> {
+    base.checked_pow(exp.try_into().ok()?)
+}
+
+// Applies `ints`, which returns `None` on overflow, to operands that must both be integers.
+fn bitwise(
+    op: TK,
+    l: Value,
+    r: Value,
+    span: Span,
+    ints: fn(i64, i64) -> Option<i64>,
+) -> Result<Value, RuntimeError> {
+    match (&l, &r) {
+        (Value::Int(l), Value::Int(r)) => ints(*l, *r)
+            .map(Value::Int)
+            .ok_or_else(|| overflow(op, span)),
+        _ => Err(bad_bin_ops(op, l, r, span)),
+    }
+}
+
+// Compares numbers and checks the ordering with `test`. NaN fails every comparison.
+fn compare(
+    op: TK,
+    l: Value,
+    r: Value,
+    span: Span,
+    test: fn(Ordering) -> bool,
+) -> Result<Value, RuntimeError> {
+    match numbers(&l, &r) {
+        Some(Numbers::Ints(l, r)) => Ok(Value::Bool(test(l.cmp(&r)))),
+        Some(Numbers::Floats(l, r)) => Ok(Value::Bool(l.partial_cmp(&r).is_some_and(test))),
+        None => Err(bad_bin_ops(op, l, r, span)),
+    }
+}
+
+fn equals(op: TK, l: Value, r: Value, span: Span) -> Result<bool, RuntimeError> {
+    match numbers(&l, &r) {
+        Some(Numbers::Ints(l, r)) => return Ok(l == r),
+        Some(Numbers::Floats(l, r)) => return Ok(l == r),
+        None => (),
+    }
+
+    match (&l, &r) {
+        (Value::String(l), Value::String(r)) => Ok(l == r),
+        (Value::Bool(l), Value::Bool(r)) => Ok(l == r),
+        (Value::Nil, Value::Nil) => Ok(true),
+        // Anything can be checked against nil, since missing fields and values are nil.
+        (Value::Nil, _) | (_, Value::Nil) => Ok(false),
+        // Objects of any kind can be compared, by identity.
+        (l, r) if l.is_object() && r.is_object() => Ok(l == r),
+        _ => Err(bad_bin_ops(op, l, r, span)),
+    }
+}
+
+/// Returns the element of `object` at `index`, which is a key for maps.
+/// `span` is where the indexing happens and is only used for errors.
+pub fn index(object: Value, index: Value, span: Span) -> Result<Value, RuntimeError> {
+    match object {
+        Value::List(list) => {
+            let list = list.borrow();
+            let position = list_index(&index, list.len(), span)?;
+            Ok(list[position].clone())
+        }
+
+        Value::Map(map) => map
+            .borrow()
+            .get(&map_key(&index, span)?)
+            .cloned()
+            .ok_or_else(|| missing_key(index, span)),
+
+        _ => Err(not_indexable(object, span)),
+    }
+}
+
+/// Replaces the element of `object` at `index` with `value`, which is returned.
+/// Maps get a new entry if there is none for the key yet.
+/// `span` is where the indexing happens and is only used for errors.
+pub fn set_index(
+    object: Value,
+    index: Value,
+    value: Value,
+    span: Span,
+) -> Result<Value, RuntimeError> {
+    match object {
+        Value::List(list) => {
+            let mut list = list.borrow_mut();
+            let position = list_index(&index, list.len(), span)?;
+            list[position] = value.clone();
+        }
+
+        Value::Map(map) => {
+            map.borrow_mut()
+                .insert(map_key(&index, span)?, value.clone());
+        }
+
+        _ => return Err(not_indexable(object, span)),
+    }
+
+    Ok(value)
+}
+
+/// Returns the map key `value` stands for.
+pub fn map_key(value: &Value, span: Span) -> Result<Key, RuntimeError> {
+    Key::new(value).ok_or_else(|| bad_key(value.clone(), span))
+}
+
+/// Returns the position `index` refers to in a list of length `len`.
+/// Negative indices count from the end, so -1 is the last element.
+pub fn list_index(index: &Value, len: usize, span: Span) -> Result<usize, RuntimeError> {
+    let n = match index {
+        Value::Int(n) => *n as f64,
+        Value::Number(n) if n.fract() == 0. => *n,
+        _ => return Err(bad_index(index.clone(), span)),
+    };
+
+    let position = if n < 0. { n + len as f64 } else { n };
+
+    if position < 0. || position >= len as f64 {
+        return Err(index_out_of_bounds(n, len, span));
+    }
+
+    Ok(position as usize)
+}
@@ -1,98 +1,389 @@
 use std::fmt;
 
-use crate::scanner::{literal::Literal, token::Token, token_kind::TokenKind};
+use super::value::Value;
+use crate::scanner::{span::Span, token::Token, token_kind::TokenKind};
 
+/// An error raised while running a program, along with the calls it unwound through.
 #[derive(Clone, Debug)]
-pub enum RuntimeError {
+pub struct RuntimeError {
+    // Boxed to keep `Result`s carrying runtime errors small.
+    pub kind: Box<RuntimeErrorKind>,
+    /// The function calls that were active when the error was raised, innermost first.
+    pub trace: Vec<Frame>,
+}
+
+/// A function call that was active when a runtime error was raised.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Frame {
+    pub function: String,
+    pub call_site: Span,
+}
+
+#[derive(Clone, Debug)]
+pub enum RuntimeErrorKind {
     BadUnOp {
         operator: TokenKind,
-        right: Literal,
-        line: usize,
+        right: Value,
+        span: Span,
     },
     BadBinOps {
-        left: Literal,
+        left: Value,
         operator: TokenKind,
-        right: Literal,
-        line: usize,
+        right: Value,
+        span: Span,
     },
     DivByZero {
-        left: Literal,
-        line: usize,
+        left: Value,
+        span: Span,
     },
     UndefinedVariable {
         name: Token,
     },
+    NotCallable {
+        callee: Value,
+        span: Span,
+    },
+    ArityMismatch {
+        expected: usize,
+        got: usize,
+        span: Span,
+    },
+    NotAnInstance {
+        value: Value,
+        span: Span,
+    },
+    UndefinedProperty {
+        name: Token,
+    },
+    SuperclassNotClass {
+        name: Token,
+        got: Value,
+    },
+    UndefinedSuperMethod {
+        superclass: String,
+        method: Token,
+    },
+    NativeFailed {
+        name: String,
+        message: String,
+        span: Span,
+    },
+    StackOverflow {
+        span: Span,
+    },
+    NotIndexable {
+        value: Value,
+        span: Span,
+    },
+    BadIndex {
+        index: Value,
+        span: Span,
+    },
+    IndexOutOfBounds {
+        index: f64,
+        len: usize,
+        span: Span,
+    },
+    BadKey {
+        key: Value,
+        span: Span,
+    },
+    MissingKey {
+        key: Value,
+        span: Span,
+    },
+    Overflow {
+        operator: TokenKind,
+        span: Span,
+    },
+    /// A value thrown by the script itself.
+    Thrown {
+        value: Value,
+        span: Span,
+    },
 }
 
-pub fn bad_un_op(operator: TokenKind, right: Literal, line: usize) -> RuntimeError {
-    RuntimeError::BadUnOp {
+pub fn bad_un_op(operator: TokenKind, right: Value, span: Span) -> RuntimeError {
+    RuntimeErrorKind::BadUnOp {
         operator,
         right,
-        line,
+        span,
     }
+    .into()
 }
 
-pub fn bad_bin_ops(
-    operator: TokenKind,
-    left: Literal,
-    right: Literal,
-    line: usize,
-) -> RuntimeError {
-    RuntimeError::BadBinOps {
+pub fn bad_bin_ops(operator: TokenKind, left: Value, right: Value, span: Span) -> RuntimeError {
+    RuntimeErrorKind::BadBinOps {
         left,
         operator,
         right,
-        line,
+        span,
     }
+    .into()
 }
 
-pub fn div_by_zero(left: Literal, line: usize) -> RuntimeError {
-    RuntimeError::DivByZero { left, line }
+pub fn div_by_zero(left: Value, span: Span) -> RuntimeError {
+    RuntimeErrorKind::DivByZero { left, span }.into()
 }
 
 pub fn undefined_variable(name: Token) -> RuntimeError {
-    RuntimeError::UndefinedVariable { name }
+    RuntimeErrorKind::UndefinedVariable { name }.into()
 }
 
-impl fmt::Display for RuntimeError {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match self {
-            RuntimeError::BadUnOp {
-                operator,
-                right,
-                line,
-            } => {
-                write!(
-                    f,
-                    "[line {line}] Invalid operand for `{:?}`: `{:?}`",
-                    operator, right
-                )
+pub fn not_callable(callee: Value, span: Span) -> RuntimeError {
+    RuntimeErrorKind::NotCallable { callee, span }.into()
+}
+
+pub fn arity_mismatch(expected: usize, got: usize, span: Span) -> RuntimeError {
+    RuntimeErrorKind::ArityMismatch {
+        expected,
+        got,
+        span,
+    }
+    .into()
+}
+
+pub fn not_an_instance(value: Value, span: Span) -> RuntimeError {
+    RuntimeErrorKind::NotAnInstance { value, span }.into()
+}
+
+pub fn undefined_property(name: Token) -> RuntimeError {
+    RuntimeErrorKind::UndefinedProperty { name }.into()
+}
+
+pub fn superclass_not_class(name: Token, got: Value) -> RuntimeError {
+    RuntimeErrorKind::SuperclassNotClass { name, got }.into()
+}
+
+pub fn undefined_super_method(superclass: String, method: Token) -> RuntimeError {
+    RuntimeErrorKind::UndefinedSuperMethod { superclass, method }.into()
+}
+
+pub fn native_failed(name: String, message: String, span: Span) -> RuntimeError {
+    RuntimeErrorKind::NativeFailed {
+        name,
+        message,
+        span,
+    }
+    .into()
+}
+
+pub fn stack_overflow(span: Span) -> RuntimeError {
+    RuntimeErrorKind::StackOverflow { span }.into()
+}
+
+pub fn not_indexable(value: Value, span: Span) -> RuntimeError {
+    RuntimeErrorKind::NotIndexable { value, span }.into()
+}
+
+pub fn bad_index(index: Value, span: Span) -> RuntimeError {
+    RuntimeErrorKind::BadIndex { index, span }.into()
+}
+
+pub fn index_out_of_bounds(index: f64, len: usize, span: Span) -> RuntimeError {
+    RuntimeErrorKind::IndexOutOfBounds { index, len, span }.into()
+}
+
+pub fn bad_key(key: Value, span: Span) -> RuntimeError {
+    RuntimeErrorKind::BadKey { key, span }.into()
+}
+
+pub fn missing_key(key: Value, span: Span) -> RuntimeError {
+    RuntimeErrorKind::MissingKey { key, span }.into()
+}
+
+pub fn overflow(operator: TokenKind, span: Span) -> RuntimeError {
+    RuntimeErrorKind::Overflow { operator, span }.into()
+}
+
+pub fn thrown(value: Value, span: Span) -> RuntimeError {
+    RuntimeErrorKind::Thrown { value, span }.into()
+}
+
+impl From<RuntimeErrorKind> for RuntimeError {
+    fn from(kind: RuntimeErrorKind) -> Self {
+        RuntimeError {
+            kind: Box::new(kind),
+            trace: Vec::new(),
+        }
+    }
+}
+
+impl RuntimeError {
+    /// Records that the error unwound out of a call to `function` made at `call_site`.
+    pub fn with_frame(mut self, function: String, call_site: Span) -> Self {
+        self.trace.push(Frame {
+            function,
+            call_site,
+        });
+        self
+    }
+
+    /// Returns the calls the error unwound through, one per line and innermost first.
+    /// Returns an empty string if the error was raised outside of any call.
+    pub fn traceback(&self) -> String {
+        if self.trace.is_empty() {
+            return String::new();
+        }
+
+        let mut out = String::from("traceback (innermost call first):");
+        let mut frames = self.trace.iter().peekable();
+
+        while let Some(frame) = frames.next() {
+            out += &format!(
+                "\n    in `{}`, called at {}:{}",
+                frame.function, frame.call_site.line, frame.call_site.column
+            );
+
+            // Deep recursion would otherwise bury everything else.
+            let mut repeats = 0;
+            while frames.next_if(|next| *next == frame).is_some() {
+                repeats += 1;
             }
+            if repeats > 0 {
+                out += &format!("\n    ... repeated {repeats} more times");
+            }
+        }
 
-            RuntimeError::BadBinOps {
+        out
+    }
+
+    /// Returns the span of source code the error is about.
+    pub fn span(&self) -> Span {
+        match self.kind.as_ref() {
+            RuntimeErrorKind::BadUnOp { span, .. }
+            | RuntimeErrorKind::BadBinOps { span, .. }
+            | RuntimeErrorKind::DivByZero { span, .. }
+            | RuntimeErrorKind::NotCallable { span, .. }
+            | RuntimeErrorKind::ArityMismatch { span, .. }
+            | RuntimeErrorKind::NotAnInstance { span, .. }
+            | RuntimeErrorKind::NativeFailed { span, .. }
+            | RuntimeErrorKind::StackOverflow { span }
+            | RuntimeErrorKind::NotIndexable { span, .. }
+            | RuntimeErrorKind::BadIndex { span, .. }
+            | RuntimeErrorKind::IndexOutOfBounds { span, .. }
+            | RuntimeErrorKind::BadKey { span, .. }
+            | RuntimeErrorKind::MissingKey { span, .. }
+            | RuntimeErrorKind::Overflow { span, .. }
+            | RuntimeErrorKind::Thrown { span, .. } => *span,
+
+            RuntimeErrorKind::UndefinedVariable { name }
+            | RuntimeErrorKind::UndefinedProperty { name }
+            | RuntimeErrorKind::SuperclassNotClass { name, .. } => name.span,
+
+            RuntimeErrorKind::UndefinedSuperMethod { method, .. } => method.span,
+        }
+    }
+
+    /// Returns a description of the error, without its location.
+    pub fn message(&self) -> String {
+        match self.kind.as_ref() {
+            RuntimeErrorKind::BadUnOp {
+                operator, right, ..
+            } => format!("Invalid operand for `{:?}`: `{:?}`", operator, right),
+
+            RuntimeErrorKind::BadBinOps {
                 left,
                 operator,
                 right,
-                line,
-            } => {
-                write!(
-                    f,
-                    "[line {line}] Invalid operands for `{:?}`: `{:?}` and `{:?}`",
-                    operator, left, right
-                )
+                ..
+            } => format!(
+                "Invalid operands for `{:?}`: `{:?}` and `{:?}`",
+                operator, left, right
+            ),
+
+            RuntimeErrorKind::DivByZero { left, .. } => {
+                format!("Cannot divide `{:?}` by zero", left)
+            }
+
+            RuntimeErrorKind::UndefinedVariable { name } => {
+                format!("Undefined variable `{}`", name.lexeme)
+            }
+
+            RuntimeErrorKind::NotCallable { callee, .. } => {
+                format!("`{:?}` is not callable", callee)
+            }
+
+            RuntimeErrorKind::ArityMismatch { expected, got, .. } => {
+                format!("Expected {expected} arguments but got {got}")
             }
 
-            RuntimeError::DivByZero { left, line } => {
-                write!(f, "[line {line}] Cannot divide `{:?}` by zero", left)
+            RuntimeErrorKind::NotAnInstance { value, .. } => {
+                format!("Only instances have properties, got `{:?}`", value)
             }
 
-            RuntimeError::UndefinedVariable { name } => {
-                write!(
-                    f,
-                    "[line {}] Undefined variable `{}`",
-                    name.line, name.lexeme
-                )
+            RuntimeErrorKind::UndefinedProperty { name } => {
+                format!("Undefined property `{}`", name.lexeme)
             }
+
+            RuntimeErrorKind::SuperclassNotClass { name, got } => format!(
+                "Superclass `{}` must be a class, got `{:?}`",
+                name.lexeme, got
+            ),
+
+            RuntimeErrorKind::UndefinedSuperMethod { superclass, method } => format!(
+                "Superclass `{}` has no method `{}`",
+                superclass, method.lexeme
+            ),
+
+            RuntimeErrorKind::NativeFailed { name, message, .. } => {
+                format!("Native function `{name}` failed: {message}")
+            }
+
+            RuntimeErrorKind::StackOverflow { .. } => "Stack overflow".into(),
+
+            RuntimeErrorKind::NotIndexable { value, .. } => {
+                format!("Only lists and maps can be indexed, got `{:?}`", value)
+            }
+
+            RuntimeErrorKind::BadIndex { index, .. } => {
+                format!("List indices must be integers, got `{:?}`", index)
+            }
+
+            RuntimeErrorKind::IndexOutOfBounds { index, len, .. } => {
+                format!("Index {index} is out of bounds for a list of length {len}")
+            }
+
+            RuntimeErrorKind::BadKey { key, .. } => format!(
+                "Map keys must be strings, numbers, booleans or nil, got `{:?}`",
+                key
+            ),
+
+            RuntimeErrorKind::MissingKey { key, .. } => {
+                format!("Key `{:?}` is not in the map", key)
+            }
+
+            RuntimeErrorKind::Overflow { operator, .. } => {
+                format!("Integer overflow in `{:?}`", operator)
+            }
+
+            RuntimeErrorKind::Thrown { value, .. } => value.to_string(),
+        }
+    }
+
+    /// Returns the property called `name` of the error, once caught by a script: its `message`,
+    /// its `line`, or the `value` that was thrown, which is nil for errors raised by the language.
+    pub fn property(&self, name: &Token) -> Result<Value, RuntimeError> {
+        match name.lexeme.as_str() {
+            "message" => Ok(Value::String(self.message())),
+            "line" => Ok(Value::Int(self.span().line as i64)),
+            "value" => match self.kind.as_ref() {
+                RuntimeErrorKind::Thrown { value, .. } => Ok(value.clone()),
+                _ => Ok(Value::Nil),
+            },
+            _ => Err(undefined_property(name.clone())),
+        }
+    }
+}
+
+impl fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[line {}] {}", self.span().line, self.message())?;
+
+        if !self.trace.is_empty() {
+            write!(f, "\n{}", self.traceback())?;
         }
+
+        Ok(())
     }
 }
@@ -0,0 +1,220 @@
+use std::{cell::RefCell, collections::HashMap, fmt, rc::Rc};
+
+use super::{
+    class::{Class, Instance},
+    function::Function,
+    map::Key,
+    native::NativeFunction,
+    runtime_error::RuntimeError,
+};
+use crate::scanner::literal::Literal;
+
+/// A value produced at runtime.
+/// Unlike a `Literal`, which only describes what can be written in source code,
+/// it can also hold heap-allocated objects, which are shared by reference.
+#[derive(Clone)]
+pub enum Value {
+    Int(i64),
+    Number(f64),
+    String(String),
+    Bool(bool),
+    Nil,
+    Function(Rc<Function>),
+    Class(Rc<Class>),
+    Instance(Rc<RefCell<Instance>>),
+    Native(Rc<NativeFunction>),
+    List(Rc<RefCell<Vec<Value>>>),
+    Map(Rc<RefCell<HashMap<Key, Value>>>),
+    /// An error caught by a `catch` block.
+    Error(Rc<RuntimeError>),
+}
+
+impl Value {
+    /// Returns `false` for `Nil` and `Bool(false)`.
+    pub fn is_truthy(&self) -> bool {
+        match self {
+            Value::Nil => false,
+            Value::Bool(b) => *b,
+            _ => true,
+        }
+    }
+
+    /// Returns true if the value lives on the heap and is compared by identity.
+    pub fn is_object(&self) -> bool {
+        matches!(
+            self,
+            Value::Function(_)
+                | Value::Class(_)
+                | Value::Instance(_)
+                | Value::Native(_)
+                | Value::List(_)
+                | Value::Map(_)
+                | Value::Error(_)
+        )
+    }
+}
+
+// Objects are equal only if they are the very same object, regardless of their contents.
+impl PartialEq for Value {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Value::Int(l), Value::Int(r)) => l == r,
+            (Value::Number(l), Value::Number(r)) => l == r,
+            (Value::String(l), Value::String(r)) => l == r,
+            (Value::Bool(l), Value::Bool(r)) => l == r,
+            (Value::Nil, Value::Nil) => true,
+            (Value::Function(l), Value::Function(r)) => Rc::ptr_eq(l, r),
+            (Value::Class(l), Value::Class(r)) => Rc::ptr_eq(l, r),
+            (Value::Instance(l), Value::Instance(r)) => Rc::ptr_eq(l, r),
+            (Value::Native(l), Value::Native(r)) => Rc::ptr_eq(l, r),
+            (Value::List(l), Value::List(r)) => Rc::ptr_eq(l, r),
+            (Value::Map(l), Value::Map(r)) => Rc::ptr_eq(l, r),
+            (Value::Error(l), Value::Error(r)) => Rc::ptr_eq(l, r),
+            _ => false,
+        }
+    }
+}
+
+impl From<&Literal> for Value {
+    fn from(literal: &Literal) -> Self {
+        match literal {
+            Literal::Int(n) => Value::Int(*n),
+            Literal::Number(n) => Value::Number(*n),
+            Literal::String(s) => Value::String(s.clone()),
+            Literal::Bool(b) => Value::Bool(*b),
+            Literal::Nil => Value::Nil,
+        }
+    }
+}
+
+impl From<Literal> for Value {
+    fn from(literal: Literal) -> Self {
+        Value::from(&literal)
+    }
+}
+
+impl From<i64> for Value {
+    fn from(n: i64) -> Self {
+        Value::Int(n)
+    }
+}
+
+impl From<f64> for Value {
+    fn from(n: f64) -> Self {
+        Value::Number(n)
+    }
+}
+
+impl From<Vec<Value>> for Value {
+    fn from(items: Vec<Value>) -> Self {
+        Value::List(Rc::new(RefCell::new(items)))
+    }
+}
+
+impl From<HashMap<Key, Value>> for Value {
+    fn from(entries: HashMap<Key, Value>) -> Self {
+        Value::Map(Rc::new(RefCell::new(entries)))
+    }
+}
+
+impl From<&str> for Value {
+    fn from(s: &str) -> Self {
+        Value::String(s.into())
+    }
+}
+
+impl From<bool> for Value {
+    fn from(value: bool) -> Self {
+        Value::Bool(value)
+    }
+}
+
+thread_local! {
+    // The lists and maps being printed, innermost last. They can contain themselves, so
+    // printing one again while it is already being printed would never end.
+    static PRINTING: RefCell<Vec<*const ()>> = const { RefCell::new(Vec::new()) };
+}
+
+// Prints the object at `ptr` with `print`, or `placeholder` if it is already being printed
+// further up.
+fn print_once(
+    ptr: *const (),
+    f: &mut fmt::Formatter<'_>,
+    placeholder: &str,
+    print: impl FnOnce(&mut fmt::Formatter<'_>) -> fmt::Result,
+) -> fmt::Result {
+    if PRINTING.with_borrow(|printing| printing.contains(&ptr)) {
+        return write!(f, "{}", placeholder);
+    }
+
+    PRINTING.with_borrow_mut(|printing| printing.push(ptr));
+    let result = print(f);
+    PRINTING.with_borrow_mut(|printing| printing.pop());
+    result
+}
+
+impl fmt::Debug for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Int(n) => f.debug_tuple("Int").field(n).finish(),
+            Value::Number(n) => f.debug_tuple("Number").field(n).finish(),
+            Value::String(s) => f.debug_tuple("String").field(s).finish(),
+            Value::Bool(b) => f.debug_tuple("Bool").field(b).finish(),
+            Value::Nil => write!(f, "Nil"),
+            Value::Function(fun) => f.debug_tuple("Function").field(fun).finish(),
+            Value::Class(class) => f.debug_tuple("Class").field(class).finish(),
+            Value::Instance(instance) => f.debug_tuple("Instance").field(instance).finish(),
+            Value::Native(native) => f.debug_tuple("Native").field(native).finish(),
+            Value::List(list) => print_once(Rc::as_ptr(list).cast(), f, "[...]", |f| {
+                f.debug_tuple("List").field(list).finish()
+            }),
+            Value::Map(map) => print_once(Rc::as_ptr(map).cast(), f, "{...}", |f| {
+                f.debug_tuple("Map").field(map).finish()
+            }),
+            Value::Error(err) => f.debug_tuple("Error").field(err).finish(),
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Int(n) => write!(f, "{}", n),
+            // Floats always show they are floats, even when they hold a whole number.
+            Value::Number(n) if n.fract() == 0. => write!(f, "{:.1}", n),
+            Value::Number(n) => write!(f, "{}", n),
+            Value::String(s) => write!(f, "{}", s),
+            Value::Bool(b) => write!(f, "{}", b),
+            Value::Nil => write!(f, "Nil"),
+            Value::Function(fun) => write!(f, "{}", fun),
+            Value::Class(class) => write!(f, "{}", class),
+            Value::Instance(instance) => write!(f, "{}", instance.borrow()),
+            Value::Native(native) => write!(f, "{}", native),
+            Value::List(list) => print_once(Rc::as_ptr(list).cast(), f, "[...]", |f| {
+                write!(f, "[")?;
+                for (i, item) in list.borrow().iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", item)?;
+                }
+                write!(f, "]")
+            }),
+            Value::Map(map) => print_once(Rc::as_ptr(map).cast(), f, "{...}", |f| {
+                let map = map.borrow();
+                let mut entries: Vec<_> = map.iter().collect();
+                entries.sort_by_key(|(key, _)| *key);
+
+                write!(f, "{{")?;
+                for (i, (key, value)) in entries.into_iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}: {}", key, value)?;
+                }
+                write!(f, "}}")
+            }),
+            Value::Error(err) => write!(f, "<error: {}>", err.message()),
+        }
+    }
+}
@@ -1,17 +1,22 @@
 #![allow(dead_code, unused_variables, unused_imports)]
 
 pub mod cursor;
+pub mod diagnostic;
 pub mod error;
 pub mod interpreter;
 pub mod parser;
+pub mod resolver;
 pub mod scanner;
+pub mod vm;
 
 use std::{cmp::Ordering, env, fs, io, path::Path};
 
 use error::Error;
 use interpreter::Interpreter;
 use parser::parse;
+use resolver::resolve;
 use scanner::tokenize;
+use vm::Vm;
 
 fn read_input() -> String {
     let mut input = String::new();
@@ -21,21 +26,40 @@ fn read_input() -> String {
     input.trim().to_owned()
 }
 
-fn run(source: String, interpreter: &mut Interpreter) -> Result<(), Error> {
-    let tokens = tokenize(source)?;
-    let stmts = parse(tokens)?;
+/// What runs programs once they are parsed and resolved.
+/// Both backends behave the same.
+enum Backend {
+    Tree(Interpreter),
+    Vm(Vm),
+}
+
+fn run(source: &str, backend: &mut Backend) -> Result<(), Error> {
+    let tokens = tokenize(source.to_owned())?;
+    let mut stmts = parse(tokens)?;
+
+    resolve(&mut stmts)?;
+
+    match backend {
+        Backend::Tree(interpreter) => interpreter.interpret(&stmts)?,
+        Backend::Vm(vm) => {
+            let script = vm.compile(&stmts)?;
+            vm.interpret(script)?
+        }
+    }
 
-    interpreter.interpret(stmts)?;
     Ok(())
 }
 
-fn run_file(path: &Path, interpreter: &mut Interpreter) -> Result<(), Error> {
+fn run_file(path: &Path, backend: &mut Backend) {
     let source =
         fs::read_to_string(path).unwrap_or_else(|_| panic!("Could not open {}", path.display()));
-    run(source, interpreter)
+
+    if let Err(err) = run(&source, backend) {
+        eprintln!("{}", err.render(&source));
+    }
 }
 
-fn run_prompt(interpreter: &mut Interpreter) {
+fn run_prompt(backend: &mut Backend) {
     println!("rlox (Ctrl+C to exit)");
 
     loop {
@@ -44,23 +68,27 @@ fn run_prompt(interpreter: &mut Interpreter) {
             continue;
         }
 
-        if let Err(msg) = run(input, interpreter) {
-            println!("Error: {msg}");
+        if let Err(err) = run(&input, backend) {
+            eprintln!("{}", err.render(&input));
         }
     }
 }
 
 fn main() {
-    let args: Vec<String> = env::args().collect();
-    let mut interpreter = Interpreter::default();
-
-    // 2 instead of 1 because the first value of args is not an user argument.
-    match args.len().cmp(&2) {
-        Ordering::Greater => panic!("Usage: rlox [script]"),
-        Ordering::Equal => match run_file(Path::new(&args[1]), &mut interpreter) {
-            Ok(_) => (),
-            Err(err) => eprintln!("Error: {err}"),
-        },
-        Ordering::Less => run_prompt(&mut interpreter),
+    // Skips the first value, which is not an user argument.
+    let mut args: Vec<String> = env::args().skip(1).collect();
+
+    let mut backend = match args.iter().position(|arg| arg == "--vm") {
+        Some(flag) => {
+            args.remove(flag);
+            Backend::Vm(Vm::default())
+        }
+        None => Backend::Tree(Interpreter::default()),
+    };
+
+    match args.len().cmp(&1) {
+        Ordering::Greater => panic!("Usage: rlox [--vm] [script]"),
+        Ordering::Equal => run_file(Path::new(&args[0]), &mut backend),
+        Ordering::Less => run_prompt(&mut backend),
     }
 }
@@ -1,17 +1,92 @@
-use std::fmt::Display;
+use std::{fmt::Display, rc::Rc};
 
+use super::stmt::FunctionDecl;
 use crate::scanner::{literal::Literal, token::Token};
 
+/// Where a local variable lives at runtime: in the environment `depth` environments above the
+/// one it is used from, at index `slot` among that environment's variables.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Local {
+    pub depth: usize,
+    pub slot: usize,
+}
+
 #[derive(Debug, Clone)]
 pub enum Expr {
     Literal(Literal),
     Unary(Token, Box<Expr>),
     Binary(Box<Expr>, Token, Box<Expr>),
     Group(Box<Expr>),
+    // The literal segments and embedded expressions of an interpolated string, in order.
+    Interpolation(Vec<Expr>),
     Ternary(Box<Expr>, Box<Expr>, Box<Expr>),
-    Variable { name: Token },
-    Assign { name: Token, value: Box<Expr> },
+    // `local` is filled in by the resolver and left as `None` for globals.
+    Variable {
+        name: Token,
+        local: Option<Local>,
+    },
+    Assign {
+        name: Token,
+        value: Box<Expr>,
+        local: Option<Local>,
+    },
+    // `target op= value`, `++target` and `target++`, where `target` is a variable, property or
+    // index expression that is only evaluated once. `op` is the binary operator applied to the
+    // target and `value`, which is 1 for increments and decrements.
+    // Postfix updates result in the target's value from before the update.
+    Update {
+        target: Box<Expr>,
+        op: Token,
+        value: Box<Expr>,
+        postfix: bool,
+    },
     Logical(Box<Expr>, Token, Box<Expr>),
+    Call {
+        callee: Box<Expr>,
+        paren: Token,
+        args: Vec<Expr>,
+    },
+    Get {
+        object: Box<Expr>,
+        name: Token,
+    },
+    Set {
+        object: Box<Expr>,
+        name: Token,
+        value: Box<Expr>,
+    },
+    This {
+        keyword: Token,
+        local: Option<Local>,
+    },
+    Super {
+        keyword: Token,
+        method: Token,
+        local: Option<Local>,
+    },
+    // An anonymous function, named `lambda`.
+    Lambda(Rc<FunctionDecl>),
+    List {
+        bracket: Token,
+        items: Vec<Expr>,
+    },
+    // Entries are kept in source order, which is the order they are evaluated in.
+    Map {
+        brace: Token,
+        entries: Vec<(Expr, Expr)>,
+    },
+    // `bracket` is the opening bracket, which errors point at.
+    Index {
+        object: Box<Expr>,
+        bracket: Token,
+        index: Box<Expr>,
+    },
+    SetIndex {
+        object: Box<Expr>,
+        bracket: Token,
+        index: Box<Expr>,
+        value: Box<Expr>,
+    },
 }
 
 impl Display for Expr {
@@ -27,13 +102,84 @@ impl Display for Expr {
 
             Expr::Group(expr) => write!(f, "(group {})", expr),
 
+            Expr::Interpolation(parts) => {
+                write!(f, "(interpolate")?;
+                for part in parts {
+                    write!(f, " {}", part)?;
+                }
+                write!(f, ")")
+            }
+
             Expr::Ternary(expr, if_, else_) => write!(f, "({} ? {} : {})", expr, if_, else_),
 
-            Expr::Variable { name } => write!(f, "(var {})", name.lexeme),
+            Expr::Variable { name, .. } => write!(f, "(var {})", name.lexeme),
+
+            Expr::Assign { name, value, .. } => write!(f, "(assign {} = {})", name.lexeme, value,),
 
-            Expr::Assign { name, value } => write!(f, "(assign {} = {})", name.lexeme, value,),
+            Expr::Update {
+                target,
+                op,
+                value,
+                postfix,
+            } => {
+                let kind = if *postfix { "post-update" } else { "update" };
+                write!(f, "({} {} {}= {})", kind, target, op.lexeme, value)
+            }
 
             Expr::Logical(l, op, r) => write!(f, "({} {} {})", l, op.lexeme, r),
+
+            Expr::Call { callee, args, .. } => {
+                write!(f, "(call {}", callee)?;
+                for arg in args {
+                    write!(f, " {}", arg)?;
+                }
+                write!(f, ")")
+            }
+
+            Expr::Get { object, name } => write!(f, "(get {} {})", object, name.lexeme),
+
+            Expr::Set {
+                object,
+                name,
+                value,
+            } => write!(f, "(set {} {} = {})", object, name.lexeme, value),
+
+            Expr::This { .. } => write!(f, "this"),
+
+            Expr::Super { method, .. } => write!(f, "(super {})", method.lexeme),
+
+            Expr::Lambda(decl) => {
+                write!(f, "(lambda")?;
+                for param in &decl.params {
+                    write!(f, " {}", param.lexeme)?;
+                }
+                write!(f, ")")
+            }
+
+            Expr::List { items, .. } => {
+                write!(f, "(list")?;
+                for item in items {
+                    write!(f, " {}", item)?;
+                }
+                write!(f, ")")
+            }
+
+            Expr::Map { entries, .. } => {
+                write!(f, "(map")?;
+                for (key, value) in entries {
+                    write!(f, " ({} {})", key, value)?;
+                }
+                write!(f, ")")
+            }
+
+            Expr::Index { object, index, .. } => write!(f, "(index {} {})", object, index),
+
+            Expr::SetIndex {
+                object,
+                index,
+                value,
+                ..
+            } => write!(f, "(set-index {} {} = {})", object, index, value),
         }
     }
 }
@@ -41,16 +187,16 @@ impl Display for Expr {
 #[cfg(test)]
 mod test {
     use super::*;
-    use crate::scanner::token_kind::TokenKind as TK;
+    use crate::scanner::{span::Span, token_kind::TokenKind as TK};
 
     #[test]
     fn test_to_string() {
         let expr = Expr::Binary(
             Box::new(Expr::Unary(
-                Token::symbol(TK::Minus, "-".into(), 1),
+                Token::symbol(TK::Minus, "-".into(), Span::default()),
                 Box::new(Expr::Literal(Literal::Number(123.0))),
             )),
-            Token::symbol(TK::Star, "*".into(), 1),
+            Token::symbol(TK::Star, "*".into(), Span::default()),
             Box::new(Expr::Group(Box::new(Expr::Literal(Literal::Number(45.67))))),
         );
 
@@ -2,18 +2,91 @@ pub mod expr;
 pub mod parse_error;
 pub mod stmt;
 
+use std::{
+    mem,
+    ops::{Deref, DerefMut},
+    rc::Rc,
+};
+
 use crate::{
     cursor::Cursor,
-    scanner::{literal::Literal, token::Token, token_kind::TokenKind as TK},
+    scanner::{literal::Literal, span::Span, token::Token, token_kind::TokenKind as TK},
 };
 
 use expr::Expr;
 use parse_error::ParseError::{self, *};
-use stmt::Stmt;
+use stmt::{Block, FunctionDecl, Stmt};
+
+/// The maximum amount of parameters a function can declare and, consequently,
+/// the maximum amount of arguments a call can pass.
+pub const MAX_ARGS: usize = 255;
+
+// The cursor over the tokens being parsed, along with the context needed to reject
+// statements that are only valid in certain places.
+struct Tokens {
+    cursor: Cursor<Token>,
+    // Labels of the loops enclosing the statement being parsed, innermost last.
+    // Unlabeled loops are `None`.
+    loops: Vec<Option<String>>,
+    // Every error recovered from so far.
+    errors: Vec<ParseError>,
+    // Where the source ends, used to report errors caused by running out of tokens.
+    eof: Span,
+}
+
+impl Tokens {
+    fn new(mut tokens: Vec<Token>) -> Self {
+        // The `Eof` token is kept out of the cursor, so that the cursor being done
+        // still means there is nothing left to parse.
+        let eof = match tokens.pop() {
+            Some(t) if t.kind == TK::Eof => t.span,
+            _ => panic!("Tokens should end with `Eof`"),
+        };
+
+        Self {
+            cursor: Cursor::new(tokens),
+            loops: Vec::new(),
+            errors: Vec::new(),
+            eof,
+        }
+    }
+
+    /// Eats the current token if it is of the specified kind, returning it.
+    /// Otherwise, returns an error.
+    fn eat_kind(&mut self, kind: TK) -> Result<Token, ParseError> {
+        match self.current() {
+            Some(t) if t.kind == kind => Ok(self.eat().expect("Should be Some")),
+            Some(t) => Err(ExpectedToken {
+                expected: kind,
+                got: Some(t.kind),
+                span: t.span,
+            }),
+            None => Err(ExpectedToken {
+                expected: kind,
+                got: None,
+                span: self.eof,
+            }),
+        }
+    }
+}
+
+impl Deref for Tokens {
+    type Target = Cursor<Token>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.cursor
+    }
+}
+
+impl DerefMut for Tokens {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.cursor
+    }
+}
 
 macro_rules! binary_expr {
     (fn $name:ident = $left:ident ($($op:ident),+) $right:ident $($rest:tt)*) => {
-        fn $name(tokens: &mut Cursor<Token>) -> Result<Expr, ParseError> {
+        fn $name(tokens: &mut Tokens) -> Result<Expr, ParseError> {
             let mut expr = $left(tokens)?;
 
             while tokens.current().is_some_and(|c| { $(c.kind == TK::$op)||+ }) {
@@ -33,7 +106,13 @@ macro_rules! binary_expr {
 // Eats tokens until the next statement boundary.
 // Used to discard tokens likely to cause cascaded errors after a parse error.
 // https://en.wikipedia.org/wiki/Cascading_failure.
-fn sync(tokens: &mut Cursor<Token>) {
+fn sync(tokens: &mut Tokens) {
+    // The offending token may have been eaten already, in which case a semicolon
+    // right behind us means we are already at the boundary.
+    if tokens.prev().is_some_and(|t| t.kind == TK::Semicolon) {
+        return;
+    }
+
     while let Some(prev_token) = tokens.eat() {
         let tk = tokens.current().map(|t| t.kind);
 
@@ -44,8 +123,9 @@ fn sync(tokens: &mut Cursor<Token>) {
 }
 
 /// Maps tokens into statements.
-pub fn parse(tokens: Vec<Token>) -> Result<Vec<Stmt>, ParseError> {
-    let mut tokens = Cursor::new(tokens);
+/// Parsing carries on after an error, so that every error in the source is returned at once.
+pub fn parse(tokens: Vec<Token>) -> Result<Vec<Stmt>, Vec<ParseError>> {
+    let mut tokens = Tokens::new(tokens);
     let mut stmts: Vec<Stmt> = vec![];
 
     while tokens.current().is_some() {
@@ -53,28 +133,146 @@ pub fn parse(tokens: Vec<Token>) -> Result<Vec<Stmt>, ParseError> {
         // must be separated because of this specific design choice:
         // Allowed:     if (foo) print "bar";     (is a statement, all good)
         // Not allowed: if (foo) var bar = "baz"; (is a declaration, not good)
-        stmts.push(declaration(&mut tokens)?);
+        if let Some(stmt) = recovering_declaration(&mut tokens) {
+            stmts.push(stmt);
+        }
     }
 
-    Ok(stmts)
+    if tokens.errors.is_empty() {
+        Ok(stmts)
+    } else {
+        Err(tokens.errors)
+    }
+}
+
+// Parses a declaration, or records the error and skips to the next statement if it fails.
+// Statement boundaries are the recovery points because errors rarely span across them.
+fn recovering_declaration(tokens: &mut Tokens) -> Option<Stmt> {
+    match declaration(tokens) {
+        Ok(stmt) => Some(stmt),
+        Err(err) => {
+            tokens.errors.push(err);
+            sync(tokens);
+            None
+        }
+    }
 }
 
 // Below are the parsing functions, where each correspond to a specific rule / production in the grammar.
 // They are organized in such a way that the deeper the function is, the higher its precedence,
 // meaning it is evaluated first.
 
-fn declaration(tokens: &mut Cursor<Token>) -> Result<Stmt, ParseError> {
+fn declaration(tokens: &mut Tokens) -> Result<Stmt, ParseError> {
     match tokens
         .current()
         .expect("Should not be called with empty cursor")
         .kind
     {
         TK::Var => var_declaration(tokens),
+        // `fun (` starts an anonymous function, which is an expression.
+        TK::Fun if !tokens.next().is_some_and(|t| t.kind == TK::LeftParenthesis) => {
+            fun_declaration(tokens)
+        }
+        TK::Class => class_declaration(tokens),
         _ => statement(tokens),
     }
 }
 
-fn var_declaration(tokens: &mut Cursor<Token>) -> Result<Stmt, ParseError> {
+fn class_declaration(tokens: &mut Tokens) -> Result<Stmt, ParseError> {
+    let class = tokens
+        .eat_kind(TK::Class)
+        .expect("Should be called when Class is the current token");
+
+    let name = tokens.eat_kind(TK::Identifier)?;
+
+    let superclass = if tokens.eat_kind(TK::Less).is_ok() {
+        Some(Expr::Variable {
+            name: tokens.eat_kind(TK::Identifier)?,
+            local: None,
+        })
+    } else {
+        None
+    };
+
+    tokens.eat_kind(TK::LeftBrace)?;
+
+    // Methods are declared just like functions, minus the `fun` keyword.
+    let mut methods: Vec<Rc<FunctionDecl>> = Vec::new();
+    while tokens.current().is_some_and(|t| t.kind != TK::RightBrace) {
+        methods.push(Rc::new(function(tokens)?));
+    }
+
+    tokens.eat_kind(TK::RightBrace)?;
+
+    Ok(Stmt::Class {
+        name,
+        superclass,
+        methods,
+    })
+}
+
+fn fun_declaration(tokens: &mut Tokens) -> Result<Stmt, ParseError> {
+    let fun = tokens
+        .eat_kind(TK::Fun)
+        .expect("Should be called when Fun is the current token");
+
+    Ok(Stmt::Function(Rc::new(function(tokens)?)))
+}
+
+// Parses everything after the `fun` keyword: name, parameters and body.
+fn function(tokens: &mut Tokens) -> Result<FunctionDecl, ParseError> {
+    let name = tokens.eat_kind(TK::Identifier)?;
+    function_rest(tokens, name)
+}
+
+// Parses the parameters and body of a function whose name is already known.
+fn function_rest(tokens: &mut Tokens, name: Token) -> Result<FunctionDecl, ParseError> {
+    let params = parameters(tokens)?;
+    let body = function_body(tokens)?;
+
+    Ok(FunctionDecl { name, params, body })
+}
+
+// Parses the braced body of a function.
+fn function_body(tokens: &mut Tokens) -> Result<Vec<Stmt>, ParseError> {
+    // Loops outside of the function can't be broken out of from within it.
+    let enclosing_loops = mem::take(&mut tokens.loops);
+    let body = block_body(tokens);
+    tokens.loops = enclosing_loops;
+
+    body
+}
+
+// Parses a parenthesized list of parameter names.
+fn parameters(tokens: &mut Tokens) -> Result<Vec<Token>, ParseError> {
+    tokens.eat_kind(TK::LeftParenthesis)?;
+
+    let mut params: Vec<Token> = Vec::new();
+    if tokens
+        .current()
+        .is_some_and(|t| t.kind != TK::RightParenthesis)
+    {
+        loop {
+            let param = tokens.eat_kind(TK::Identifier)?;
+
+            if params.len() >= MAX_ARGS {
+                return Err(TooManyParameters { span: param.span });
+            }
+
+            params.push(param);
+
+            if tokens.eat_kind(TK::Comma).is_err() {
+                break;
+            }
+        }
+    }
+
+    tokens.eat_kind(TK::RightParenthesis)?;
+
+    Ok(params)
+}
+
+fn var_declaration(tokens: &mut Tokens) -> Result<Stmt, ParseError> {
     let var = tokens
         .eat_kind(TK::Var)
         .expect("Should be called when Var is the current token");
@@ -92,7 +290,7 @@ fn var_declaration(tokens: &mut Cursor<Token>) -> Result<Stmt, ParseError> {
     Ok(Stmt::Var { name, init })
 }
 
-fn statement(tokens: &mut Cursor<Token>) -> Result<Stmt, ParseError> {
+fn statement(tokens: &mut Tokens) -> Result<Stmt, ParseError> {
     match tokens
         .current()
         .expect("Should not be called with empty cursor")
@@ -101,12 +299,76 @@ fn statement(tokens: &mut Cursor<Token>) -> Result<Stmt, ParseError> {
         TK::Print => print_stmt(tokens),
         TK::LeftBrace => block(tokens),
         TK::If => if_stmt(tokens),
-        TK::While => while_stmt(tokens),
+        TK::While => while_stmt(tokens, None),
+        TK::For => for_stmt(tokens, None),
+        TK::Return => return_stmt(tokens),
+        TK::Break | TK::Continue => jump_stmt(tokens),
+        TK::Try => try_stmt(tokens),
+        TK::Throw => throw_stmt(tokens),
+        TK::Identifier if tokens.next().is_some_and(|t| t.kind == TK::Colon) => {
+            labeled_stmt(tokens)
+        }
         _ => expr_stmt(tokens),
     }
 }
 
-fn print_stmt(tokens: &mut Cursor<Token>) -> Result<Stmt, ParseError> {
+// A label names a loop so that nested loops can break out of or continue it:
+// outer: while (a) { while (b) { break outer; } }
+fn labeled_stmt(tokens: &mut Tokens) -> Result<Stmt, ParseError> {
+    let label = tokens
+        .eat_kind(TK::Identifier)
+        .expect("Should be called when Identifier is the current token");
+
+    tokens
+        .eat_kind(TK::Colon)
+        .expect("Should be called when Colon is the next token");
+
+    match tokens.current().map(|t| t.kind) {
+        Some(TK::While) => while_stmt(tokens, Some(label)),
+        Some(TK::For) => for_stmt(tokens, Some(label)),
+        _ => Err(BadLabelTarget { span: label.span }),
+    }
+}
+
+// Parses the body of a loop, during which `break` and `continue` are allowed.
+fn loop_body(tokens: &mut Tokens, label: &Option<Token>) -> Result<Stmt, ParseError> {
+    tokens.loops.push(label.as_ref().map(|l| l.lexeme.clone()));
+    let body = statement(tokens);
+    tokens.loops.pop();
+
+    body
+}
+
+fn jump_stmt(tokens: &mut Tokens) -> Result<Stmt, ParseError> {
+    let keyword = tokens
+        .eat()
+        .expect("Should be called when Break or Continue is the current token");
+
+    let label = tokens.eat_kind(TK::Identifier).ok();
+
+    if tokens.loops.is_empty() {
+        return Err(JumpOutsideLoop {
+            keyword: keyword.kind,
+            span: keyword.span,
+        });
+    }
+
+    if let Some(label) = &label {
+        if !tokens.loops.contains(&Some(label.lexeme.clone())) {
+            return Err(UndefinedLabel { span: label.span });
+        }
+    }
+
+    tokens.eat_kind(TK::Semicolon)?;
+
+    Ok(match keyword.kind {
+        TK::Break => Stmt::Break { keyword, label },
+        TK::Continue => Stmt::Continue { keyword, label },
+        _ => panic!("Should be called when Break or Continue is the current token"),
+    })
+}
+
+fn print_stmt(tokens: &mut Tokens) -> Result<Stmt, ParseError> {
     let print = tokens
         .eat_kind(TK::Print)
         .expect("Should be called when print is the current token");
@@ -118,7 +380,7 @@ fn print_stmt(tokens: &mut Cursor<Token>) -> Result<Stmt, ParseError> {
     Ok(Stmt::Print(value))
 }
 
-fn expr_stmt(tokens: &mut Cursor<Token>) -> Result<Stmt, ParseError> {
+fn expr_stmt(tokens: &mut Tokens) -> Result<Stmt, ParseError> {
     let expr = expression(tokens)?;
 
     tokens.eat_kind(TK::Semicolon)?;
@@ -126,22 +388,28 @@ fn expr_stmt(tokens: &mut Cursor<Token>) -> Result<Stmt, ParseError> {
     Ok(Stmt::Expr(expr))
 }
 
-fn block(tokens: &mut Cursor<Token>) -> Result<Stmt, ParseError> {
-    let left_brace = tokens
-        .eat_kind(TK::LeftBrace)
-        .expect("Should be called when LeftBrace is the current token");
+fn block(tokens: &mut Tokens) -> Result<Stmt, ParseError> {
+    Ok(Stmt::Block(Block::new(block_body(tokens)?)))
+}
+
+// Parses the statements between a pair of braces, braces included.
+// Shared by blocks and function bodies.
+fn block_body(tokens: &mut Tokens) -> Result<Vec<Stmt>, ParseError> {
+    tokens.eat_kind(TK::LeftBrace)?;
 
     let mut stmts: Vec<Stmt> = Vec::new();
     while tokens.current().is_some_and(|t| t.kind != TK::RightBrace) {
-        stmts.push(declaration(tokens)?);
+        if let Some(stmt) = recovering_declaration(tokens) {
+            stmts.push(stmt);
+        }
     }
 
     tokens.eat_kind(TK::RightBrace)?;
 
-    Ok(Stmt::Block(stmts))
+    Ok(stmts)
 }
 
-fn if_stmt(tokens: &mut Cursor<Token>) -> Result<Stmt, ParseError> {
+fn if_stmt(tokens: &mut Tokens) -> Result<Stmt, ParseError> {
     let if_ = tokens
         .eat_kind(TK::If)
         .expect("Should be called when If is the current token");
@@ -167,7 +435,7 @@ fn if_stmt(tokens: &mut Cursor<Token>) -> Result<Stmt, ParseError> {
     })
 }
 
-fn while_stmt(tokens: &mut Cursor<Token>) -> Result<Stmt, ParseError> {
+fn while_stmt(tokens: &mut Tokens, label: Option<Token>) -> Result<Stmt, ParseError> {
     let while_ = tokens
         .eat_kind(TK::While)
         .expect("Should be called when While is the current token");
@@ -178,19 +446,145 @@ fn while_stmt(tokens: &mut Cursor<Token>) -> Result<Stmt, ParseError> {
 
     tokens.eat_kind(TK::RightParenthesis)?;
 
-    let body = Box::new(statement(tokens)?);
+    let body = Box::new(loop_body(tokens, &label)?);
+
+    Ok(Stmt::While {
+        condition,
+        body,
+        increment: None,
+        label,
+    })
+}
+
+// There is no dedicated for loop statement, instead, it is desugared into a while loop:
+// for (init; condition; increment) body
+// Becomes:
+// { init; while (condition) body }
+// Where the while loop runs the increment after each iteration, including `continue`d ones.
+fn for_stmt(tokens: &mut Tokens, label: Option<Token>) -> Result<Stmt, ParseError> {
+    let for_ = tokens
+        .eat_kind(TK::For)
+        .expect("Should be called when For is the current token");
+
+    tokens.eat_kind(TK::LeftParenthesis)?;
+
+    let init = match tokens.current().map(|t| t.kind) {
+        Some(TK::Semicolon) => {
+            tokens.eat();
+            None
+        }
+        Some(TK::Var) => Some(var_declaration(tokens)?),
+        _ => Some(expr_stmt(tokens)?),
+    };
+
+    // An omitted condition loops forever.
+    let condition = if tokens.current().is_some_and(|t| t.kind == TK::Semicolon) {
+        Expr::Literal(Literal::Bool(true))
+    } else {
+        expression(tokens)?
+    };
+
+    tokens.eat_kind(TK::Semicolon)?;
+
+    let increment = if tokens
+        .current()
+        .is_some_and(|t| t.kind == TK::RightParenthesis)
+    {
+        None
+    } else {
+        Some(expression(tokens)?)
+    };
+
+    tokens.eat_kind(TK::RightParenthesis)?;
+
+    let mut body = Stmt::While {
+        condition,
+        body: Box::new(loop_body(tokens, &label)?),
+        increment,
+        label,
+    };
+
+    // The extra block scopes the initializer's variable to the loop.
+    if let Some(init) = init {
+        body = Stmt::Block(Block::new(vec![init, body]));
+    }
+
+    Ok(body)
+}
+
+fn return_stmt(tokens: &mut Tokens) -> Result<Stmt, ParseError> {
+    let keyword = tokens
+        .eat_kind(TK::Return)
+        .expect("Should be called when Return is the current token");
+
+    let value = if tokens.current().is_some_and(|t| t.kind == TK::Semicolon) {
+        None
+    } else {
+        Some(expression(tokens)?)
+    };
+
+    tokens.eat_kind(TK::Semicolon)?;
+
+    Ok(Stmt::Return { keyword, value })
+}
+
+// try { ... } catch (e) { ... } finally { ... }
+// Either of `catch` and `finally` can be left out, but not both.
+fn try_stmt(tokens: &mut Tokens) -> Result<Stmt, ParseError> {
+    let keyword = tokens
+        .eat_kind(TK::Try)
+        .expect("Should be called when Try is the current token");
+
+    let body = Block::new(block_body(tokens)?);
+
+    let catch = if tokens.eat_kind(TK::Catch).is_ok() {
+        tokens.eat_kind(TK::LeftParenthesis)?;
+        let name = tokens.eat_kind(TK::Identifier)?;
+        tokens.eat_kind(TK::RightParenthesis)?;
+
+        Some((name, Block::new(block_body(tokens)?)))
+    } else {
+        None
+    };
+
+    let finally = if tokens.eat_kind(TK::Finally).is_ok() {
+        Some(Block::new(block_body(tokens)?))
+    } else {
+        None
+    };
+
+    if catch.is_none() && finally.is_none() {
+        return Err(TryWithoutHandler { span: keyword.span });
+    }
+
+    Ok(Stmt::Try {
+        keyword,
+        body,
+        catch,
+        finally,
+    })
+}
+
+fn throw_stmt(tokens: &mut Tokens) -> Result<Stmt, ParseError> {
+    let keyword = tokens
+        .eat_kind(TK::Throw)
+        .expect("Should be called when Throw is the current token");
+
+    let value = expression(tokens)?;
+
+    tokens.eat_kind(TK::Semicolon)?;
 
-    Ok(Stmt::While { condition, body })
+    Ok(Stmt::Throw { keyword, value })
 }
 
-fn expression(tokens: &mut Cursor<Token>) -> Result<Expr, ParseError> {
+fn expression(tokens: &mut Tokens) -> Result<Expr, ParseError> {
     assignment(tokens)
 }
 
-fn assignment(tokens: &mut Cursor<Token>) -> Result<Expr, ParseError> {
+fn assignment(tokens: &mut Tokens) -> Result<Expr, ParseError> {
     // Even though we error if expr is not a variable later on, we still search for
     // anything ternary below, as long as it resolves to a variable.
-    // This allows things such as: a ? b : c = d and: a.b = c
+    // This allows things such as: a ? b : c = d, a.b = c and: a[b] = c
     let expr = ternary(tokens)?;
 
     if tokens.current().is_some_and(|t| t.kind == TK::Equal) {
@@ -198,21 +592,85 @@ fn assignment(tokens: &mut Cursor<Token>) -> Result<Expr, ParseError> {
         // We recursively go for another assignment to allow operator chaining (--1).
         let value = assignment(tokens)?;
 
-        if let Expr::Variable { name } = expr {
-            return Ok(Expr::Assign {
+        return match expr {
+            Expr::Variable { name, .. } => Ok(Expr::Assign {
                 name,
                 value: Box::new(value),
-            });
-        } else {
+                local: None,
+            }),
+
+            // a.b = c is a property assignment, rather than an assignment to the value of a.b.
+            Expr::Get { object, name } => Ok(Expr::Set {
+                object,
+                name,
+                value: Box::new(value),
+            }),
+
+            // Likewise, a[b] = c assigns to an element of a.
+            Expr::Index {
+                object,
+                bracket,
+                index,
+            } => Ok(Expr::SetIndex {
+                object,
+                bracket,
+                index,
+                value: Box::new(value),
+            }),
+
             // a + b = c errors because a + c does not resolve to a variable.
-            return Err(BadAssignmentTarget { line: equal.line });
-        }
+            _ => Err(BadAssignmentTarget { span: equal.span }),
+        };
+    }
+
+    // a += b applies `+` to a and b, then assigns the result back to a.
+    let compound = tokens.current().and_then(|t| match t.kind {
+        TK::PlusEqual => Some((TK::Plus, "+")),
+        TK::MinusEqual => Some((TK::Minus, "-")),
+        TK::StarEqual => Some((TK::Star, "*")),
+        TK::SlashEqual => Some((TK::Slash, "/")),
+        _ => None,
+    });
+
+    if let Some((kind, lexeme)) = compound {
+        let equal = tokens.eat().unwrap();
+        let value = assignment(tokens)?;
+        return Ok(Expr::Update {
+            target: Box::new(update_target(expr, equal.span)?),
+            op: Token::symbol(kind, lexeme.into(), equal.span),
+            value: Box::new(value),
+            postfix: false,
+        });
     }
 
     Ok(expr)
 }
 
-fn ternary(tokens: &mut Cursor<Token>) -> Result<Expr, ParseError> {
+// Checks that `expr` can be updated in place, `span` being where the update operator is.
+fn update_target(expr: Expr, span: Span) -> Result<Expr, ParseError> {
+    match expr {
+        Expr::Variable { .. } | Expr::Get { .. } | Expr::Index { .. } => Ok(expr),
+        _ => Err(BadAssignmentTarget { span }),
+    }
+}
+
+// Turns `++` or `--` into an update that adds or subtracts 1 from `target`.
+fn increment(target: Expr, op: Token, postfix: bool) -> Result<Expr, ParseError> {
+    let (kind, lexeme) = match op.kind {
+        TK::PlusPlus => (TK::Plus, "+"),
+        TK::MinusMinus => (TK::Minus, "-"),
+        _ => panic!("Should be called with PlusPlus or MinusMinus"),
+    };
+
+    Ok(Expr::Update {
+        target: Box::new(update_target(target, op.span)?),
+        op: Token::symbol(kind, lexeme.into(), op.span),
+        value: Box::new(Expr::Literal(Literal::Int(1))),
+        postfix,
+    })
+}
+
+fn ternary(tokens: &mut Tokens) -> Result<Expr, ParseError> {
     let mut expr = or(tokens)?;
 
     if tokens.current().is_some_and(|t| t.kind == TK::Question) {
@@ -229,7 +687,7 @@ fn ternary(tokens: &mut Cursor<Token>) -> Result<Expr, ParseError> {
     Ok(expr)
 }
 
-fn or(tokens: &mut Cursor<Token>) -> Result<Expr, ParseError> {
+fn or(tokens: &mut Tokens) -> Result<Expr, ParseError> {
     let mut expr = and(tokens)?;
 
     while let Ok(op) = tokens.eat_kind(TK::Or) {
@@ -240,46 +698,142 @@ fn or(tokens: &mut Cursor<Token>) -> Result<Expr, ParseError> {
     Ok(expr)
 }
 
-fn and(tokens: &mut Cursor<Token>) -> Result<Expr, ParseError> {
-    let mut expr = equality(tokens)?;
+fn and(tokens: &mut Tokens) -> Result<Expr, ParseError> {
+    let mut expr = bit_or(tokens)?;
 
     while let Ok(op) = tokens.eat_kind(TK::And) {
-        let right = equality(tokens)?;
+        let right = bit_or(tokens)?;
         expr = Expr::Logical(Box::new(expr), op, Box::new(right));
     }
 
     Ok(expr)
 }
 
+// Like in C, bitwise operators bind looser than comparisons, and shifts bind tighter.
 binary_expr!(
+    fn bit_or = bit_xor (Pipe) bit_xor
+    fn bit_xor = bit_and (Caret) bit_and
+    fn bit_and = equality (Ampersand) equality
     fn equality = comparison (BangEqual, EqualEqual) comparison
-    fn comparison = term (Greater, GreaterEqual, Less, LessEqual) term
+    fn comparison = shift (Greater, GreaterEqual, Less, LessEqual) shift
+    fn shift = term (LessLess, GreaterGreater) term
     fn term = factor (Plus, Minus) factor
-    fn factor = unary (Star, Slash) unary
+    fn factor = unary (Star, Slash, Percent) unary
 );
 
-fn unary(tokens: &mut Cursor<Token>) -> Result<Expr, ParseError> {
+fn unary(tokens: &mut Tokens) -> Result<Expr, ParseError> {
     if tokens
         .current()
-        .is_some_and(|t| matches!(t.kind, TK::Bang | TK::Minus))
+        .is_some_and(|t| matches!(t.kind, TK::Bang | TK::Minus | TK::Tilde))
     {
         let op = tokens.eat().unwrap();
         let right = unary(tokens)?;
         return Ok(Expr::Unary(op, Box::new(right)));
     }
 
-    literal(tokens)
+    if tokens
+        .current()
+        .is_some_and(|t| matches!(t.kind, TK::PlusPlus | TK::MinusMinus))
+    {
+        let op = tokens.eat().unwrap();
+        let target = unary(tokens)?;
+        return increment(target, op, false);
+    }
+
+    power(tokens)
 }
 
-fn literal(tokens: &mut Cursor<Token>) -> Result<Expr, ParseError> {
-    // Lazy evaluation is needed, otherwise `tokens.prev` will error. This is why `ok_or` is not used.
-    let t = if let Some(t) = tokens.current() {
-        t
-    } else {
-        return Err(ExpectedAnyToken {
-            line: tokens.prev().expect("`tokens` should not be empty").line,
-        });
-    };
+// Exponentiation binds tighter than the unary operators before it, so -2 ** 2 is -(2 ** 2),
+// but its exponent is parsed as a unary to allow 2 ** -1.
+// Going back up to unary also makes it right-associative: 2 ** 3 ** 2 is 2 ** (3 ** 2).
+fn power(tokens: &mut Tokens) -> Result<Expr, ParseError> {
+    let expr = postfix(tokens)?;
+
+    if let Ok(op) = tokens.eat_kind(TK::StarStar) {
+        let right = unary(tokens)?;
+        return Ok(Expr::Binary(Box::new(expr), op, Box::new(right)));
+    }
+
+    Ok(expr)
+}
+
+fn postfix(tokens: &mut Tokens) -> Result<Expr, ParseError> {
+    let expr = call(tokens)?;
+
+    if tokens
+        .current()
+        .is_some_and(|t| matches!(t.kind, TK::PlusPlus | TK::MinusMinus))
+    {
+        let op = tokens.eat().unwrap();
+        return increment(expr, op, true);
+    }
+
+    Ok(expr)
+}
+
+fn call(tokens: &mut Tokens) -> Result<Expr, ParseError> {
+    let mut expr = literal(tokens)?;
+
+    // Loops to allow chained calls, property accesses and indexing, such as: foo()().bar[0]()
+    loop {
+        if let Ok(paren) = tokens.eat_kind(TK::LeftParenthesis) {
+            expr = finish_call(tokens, expr, paren)?;
+        } else if let Ok(bracket) = tokens.eat_kind(TK::LeftBracket) {
+            let index = expression(tokens)?;
+            tokens.eat_kind(TK::RightBracket)?;
+            expr = Expr::Index {
+                object: Box::new(expr),
+                bracket,
+                index: Box::new(index),
+            };
+        } else if tokens.eat_kind(TK::Dot).is_ok() {
+            let name = tokens.eat_kind(TK::Identifier)?;
+            expr = Expr::Get {
+                object: Box::new(expr),
+                name,
+            };
+        } else {
+            break;
+        }
+    }
+
+    Ok(expr)
+}
+
+// Parses the arguments of a call whose opening parenthesis was just eaten.
+fn finish_call(tokens: &mut Tokens, callee: Expr, paren: Token) -> Result<Expr, ParseError> {
+    let mut args: Vec<Expr> = Vec::new();
+
+    if tokens
+        .current()
+        .is_some_and(|t| t.kind != TK::RightParenthesis)
+    {
+        loop {
+            if args.len() >= MAX_ARGS {
+                return Err(TooManyArguments { span: paren.span });
+            }
+
+            args.push(expression(tokens)?);
+
+            if tokens.eat_kind(TK::Comma).is_err() {
+                break;
+            }
+        }
+    }
+
+    tokens.eat_kind(TK::RightParenthesis)?;
+
+    Ok(Expr::Call {
+        callee: Box::new(callee),
+        paren,
+        args,
+    })
+}
+
+fn literal(tokens: &mut Tokens) -> Result<Expr, ParseError> {
+    let t = tokens
+        .current()
+        .ok_or(ExpectedAnyToken { span: tokens.eof })?;
 
     if t.kind.is_lit() {
         let tok = tokens.eat().unwrap();
@@ -288,19 +842,180 @@ fn literal(tokens: &mut Cursor<Token>) -> Result<Expr, ParseError> {
         })));
     }
 
+    if t.kind == TK::InterpolationStart {
+        return interpolation(tokens);
+    }
+
     variable(tokens)
 }
 
-fn variable(tokens: &mut Cursor<Token>) -> Result<Expr, ParseError> {
+// "a${b}c${d}" is scanned as: InterpolationStart(a) b InterpolationMid(c) d InterpolationEnd().
+fn interpolation(tokens: &mut Tokens) -> Result<Expr, ParseError> {
+    let mut parts = Vec::new();
+    let mut segment = tokens
+        .eat_kind(TK::InterpolationStart)
+        .expect("Should be called when InterpolationStart is the current token");
+
+    loop {
+        // Empty segments add nothing to the string.
+        match segment.literal {
+            Some(Literal::String(s)) if s.is_empty() => (),
+            Some(literal) => parts.push(Expr::Literal(literal)),
+            None => panic!("Expected token `{:?}` to have a literal", segment.kind),
+        }
+
+        if segment.kind == TK::InterpolationEnd {
+            break;
+        }
+
+        parts.push(expression(tokens)?);
+
+        segment = match tokens.eat_kind(TK::InterpolationMid) {
+            Ok(mid) => mid,
+            Err(_) => tokens.eat_kind(TK::InterpolationEnd)?,
+        };
+    }
+
+    Ok(Expr::Interpolation(parts))
+}
+
+fn variable(tokens: &mut Tokens) -> Result<Expr, ParseError> {
     if tokens.current().is_some_and(|t| t.kind == TK::Identifier) {
         let name = tokens.eat().unwrap();
-        return Ok(Expr::Variable { name });
+        return Ok(Expr::Variable { name, local: None });
+    }
+
+    if let Ok(keyword) = tokens.eat_kind(TK::This) {
+        return Ok(Expr::This {
+            keyword,
+            local: None,
+        });
+    }
+
+    // A bare `super` means nothing, it must always be followed by a method access.
+    if let Ok(keyword) = tokens.eat_kind(TK::Super) {
+        tokens.eat_kind(TK::Dot)?;
+        let method = tokens.eat_kind(TK::Identifier)?;
+        return Ok(Expr::Super {
+            keyword,
+            method,
+            local: None,
+        });
     }
 
-    group(tokens)
+    list(tokens)
 }
 
-fn group(tokens: &mut Cursor<Token>) -> Result<Expr, ParseError> {
+fn list(tokens: &mut Tokens) -> Result<Expr, ParseError> {
+    let Ok(bracket) = tokens.eat_kind(TK::LeftBracket) else {
+        return map(tokens);
+    };
+
+    let mut items = Vec::new();
+
+    if tokens
+        .current()
+        .is_some_and(|t| t.kind != TK::RightBracket)
+    {
+        loop {
+            items.push(expression(tokens)?);
+
+            if tokens.eat_kind(TK::Comma).is_err() {
+                break;
+            }
+        }
+    }
+
+    tokens.eat_kind(TK::RightBracket)?;
+
+    Ok(Expr::List { bracket, items })
+}
+
+// A brace at the start of a statement is always a block, since `statement` looks for blocks
+// before expressions. So a map literal can only start a statement if it is grouped: ({}).
+fn map(tokens: &mut Tokens) -> Result<Expr, ParseError> {
+    let Ok(brace) = tokens.eat_kind(TK::LeftBrace) else {
+        return lambda(tokens);
+    };
+
+    let mut entries = Vec::new();
+
+    if tokens.current().is_some_and(|t| t.kind != TK::RightBrace) {
+        loop {
+            let key = expression(tokens)?;
+            tokens.eat_kind(TK::Colon)?;
+            let value = expression(tokens)?;
+            entries.push((key, value));
+
+            if tokens.eat_kind(TK::Comma).is_err() {
+                break;
+            }
+        }
+    }
+
+    tokens.eat_kind(TK::RightBrace)?;
+
+    Ok(Expr::Map { brace, entries })
+}
+
+// Anonymous functions come in two forms: fun (a, b) { return a + b; } and (a, b) => a + b.
+// The body of the arrow form is either a single expression, which is what it returns, or a
+// block like in the other form. So `() => {}` returns nothing rather than an empty map.
+fn lambda(tokens: &mut Tokens) -> Result<Expr, ParseError> {
+    if let Ok(fun) = tokens.eat_kind(TK::Fun) {
+        let name = Token::symbol(TK::Identifier, "lambda".into(), fun.span);
+        return Ok(Expr::Lambda(Rc::new(function_rest(tokens, name)?)));
+    }
+
+    if !is_arrow_function(tokens) {
+        return group(tokens);
+    }
+
+    let paren = tokens.current().expect("Should be a LeftParenthesis");
+    let name = Token::symbol(TK::Identifier, "lambda".into(), paren.span);
+    let params = parameters(tokens)?;
+    let arrow = tokens.eat_kind(TK::Arrow)?;
+
+    let body = if tokens.current().is_some_and(|t| t.kind == TK::LeftBrace) {
+        function_body(tokens)?
+    } else {
+        vec![Stmt::Return {
+            keyword: arrow,
+            value: Some(expression(tokens)?),
+        }]
+    };
+
+    Ok(Expr::Lambda(Rc::new(FunctionDecl { name, params, body })))
+}
+
+// The parameters of an arrow function look like a grouping until the arrow after them,
+// so they are looked ahead for without eating anything.
+fn is_arrow_function(tokens: &Tokens) -> bool {
+    let kind = |offset| tokens.peek(offset).map(|t| t.kind);
+
+    if kind(0) != Some(TK::LeftParenthesis) {
+        return false;
+    }
+
+    let mut offset = 1;
+    if kind(offset) != Some(TK::RightParenthesis) {
+        loop {
+            if kind(offset) != Some(TK::Identifier) {
+                return false;
+            }
+
+            offset += 1;
+            if kind(offset) != Some(TK::Comma) {
+                break;
+            }
+            offset += 1;
+        }
+    }
+
+    kind(offset) == Some(TK::RightParenthesis) && kind(offset + 1) == Some(TK::Arrow)
+}
+
+fn group(tokens: &mut Tokens) -> Result<Expr, ParseError> {
     // The opening parenthesis.
     match tokens.current() {
         Some(t) if t.kind == TK::LeftParenthesis => (),
@@ -309,7 +1024,7 @@ fn group(tokens: &mut Cursor<Token>) -> Result<Expr, ParseError> {
             return Err(ExpectedToken {
                 expected: TK::LeftParenthesis,
                 got: None,
-                line: tokens.prev().expect("`tokens` should not be empty").line,
+                span: tokens.eof,
             });
         }
     };
@@ -323,7 +1038,7 @@ fn group(tokens: &mut Cursor<Token>) -> Result<Expr, ParseError> {
 }
 
 // Should be ran by the last expression function when there is no more parseable expressions.
-fn last_parse_error(tokens: &mut Cursor<Token>) -> ParseError {
+fn last_parse_error(tokens: &mut Tokens) -> ParseError {
     if let Some(t) = tokens.eat() {
         return if matches!(
             t.kind,
@@ -336,27 +1051,38 @@ fn last_parse_error(tokens: &mut Cursor<Token>) -> ParseError {
                 | TK::Plus
                 | TK::Slash
                 | TK::Star
+                | TK::StarStar
+                | TK::Percent
+                | TK::Ampersand
+                | TK::Pipe
+                | TK::Caret
+                | TK::LessLess
+                | TK::GreaterGreater
         ) {
             ExpectedAnyLeftOperand {
                 operator: t.kind,
-                line: t.line,
+                span: t.span,
             }
         } else {
             NotParseable {
                 token: t.kind,
-                line: t.line,
+                span: t.span,
             }
         };
     }
 
-    // TODO! Add actual line number to the error, which will require the last line to be known.
-    ExpectedAnyToken {
-        line: tokens.prev().expect("`tokens` should not be empty").line,
-    }
+    ExpectedAnyToken { span: tokens.eof }
 }
 
 mod tests {
-    use crate::{parser::parse, scanner::tokenize};
+    use crate::{
+        parser::{
+            expr::Expr,
+            parse,
+            stmt::{Block, Stmt},
+        },
+        scanner::tokenize,
+    };
 
     #[test]
     fn test() {
@@ -364,4 +1090,140 @@ mod tests {
         let ast = parse(tokens).unwrap();
         println!("{:#?}", ast);
     }
+
+    #[test]
+    fn test_for_desugaring() {
+        let tokens = tokenize("for (var i = 0; i < 3; i = i + 1) print i;".to_string()).unwrap();
+        let ast = parse(tokens).unwrap();
+
+        let Stmt::Block(Block { stmts: outer, .. }) = &ast[0] else {
+            panic!("Expected a block, got {:?}", ast[0]);
+        };
+        assert!(matches!(outer[0], Stmt::Var { .. }));
+
+        let Stmt::While { increment, .. } = &outer[1] else {
+            panic!("Expected a while loop, got {:?}", outer[1]);
+        };
+        assert!(increment.is_some());
+
+        let tokens = tokenize("for (;;) print 1;".to_string()).unwrap();
+        let ast = parse(tokens).unwrap();
+        assert!(matches!(&ast[0], Stmt::While { body, .. } if matches!(**body, Stmt::Print(_))));
+    }
+
+    #[test]
+    fn test_multiple_errors() {
+        let tokens = tokenize("var = 1; print 2; 1 +; { var; } print; fun f( {}".to_string()).unwrap();
+        let errors = parse(tokens).unwrap_err();
+
+        assert_eq!(errors.len(), 5);
+    }
+
+    #[test]
+    fn test_jump_outside_loop() {
+        let parse_source = |source: &str| parse(tokenize(source.to_string()).unwrap());
+
+        assert!(parse_source("while (true) { if (true) break; continue; }").is_ok());
+        assert!(parse_source("a: while (true) for (;;) break a;").is_ok());
+        assert!(parse_source("break;").is_err());
+        assert!(parse_source("while (true) { fun f() { continue; } }").is_err());
+        assert!(parse_source("a: while (true) {} while (true) break a;").is_err());
+    }
+
+    #[test]
+    fn test_try() {
+        let parse_source = |source: &str| parse(tokenize(source.to_string()).unwrap());
+
+        assert!(parse_source("try {} catch (e) {} finally {} try {} finally {}").is_ok());
+        assert!(parse_source("while (true) try { break; } catch (e) { throw e; }").is_ok());
+        assert!(parse_source("try {}").is_err());
+        assert!(parse_source("try {} catch {}").is_err());
+        assert!(parse_source("throw;").is_err());
+    }
+
+    #[test]
+    fn test_precedence() {
+        let expr = |source: &str| {
+            let ast = parse(tokenize(format!("{source};")).unwrap()).unwrap();
+            match &ast[0] {
+                Stmt::Expr(expr) => expr.to_string(),
+                stmt => panic!("Expected an expression, got {:?}", stmt),
+            }
+        };
+
+        assert_eq!(expr("-2 ** 2"), "(- (** 2 2))");
+        assert_eq!(expr("2 ** 3 ** -1"), "(** 2 (** 3 (- 1)))");
+        assert_eq!(expr("1 | 2 ^ 3 & 4 == 5"), "(| 1 (^ 2 (& 3 (== 4 5))))");
+        assert_eq!(expr("1 << 2 + 3 < 4 % 5"), "(< (<< 1 (+ 2 3)) (% 4 5))");
+    }
+
+    #[test]
+    fn test_updates() {
+        let parse_source = |source: &str| parse(tokenize(source.to_string()).unwrap());
+
+        let ast = parse_source("a.b[c] *= 2; -x++; ++y;").unwrap();
+        let exprs: Vec<_> = ast.iter().map(|stmt| format!("{:?}", stmt)).collect();
+        assert!(exprs[0].starts_with("Expr(Update { target: Index"));
+        assert!(exprs[1].starts_with("Expr(Unary("));
+        assert!(exprs[2].contains("postfix: false"));
+
+        assert!(parse_source("a + b += 1;").is_err());
+        assert!(parse_source("f()++;").is_err());
+        assert!(parse_source("++1;").is_err());
+
+        // `--` is always one token, like in C, so it needs a space to mean minus a negation.
+        let errors = parse_source("5--2;").unwrap_err();
+        assert_eq!(errors[0].message(), "Invalid assignment target");
+        assert_eq!(parse_source("5 - -2;").unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_lambdas() {
+        let parse_source = |source: &str| parse(tokenize(source.to_string()).unwrap());
+
+        let ast = parse_source("fun (a) { return a; }; f(() => 1, (a, b) => a + b); (a);").unwrap();
+        assert!(matches!(&ast[0], Stmt::Expr(Expr::Lambda(decl)) if decl.params.len() == 1));
+        let Stmt::Expr(call) = &ast[1] else {
+            panic!("Expected an expression, got {:?}", ast[1]);
+        };
+        assert_eq!(call.to_string(), "(call (var f) (lambda) (lambda a b))");
+        assert!(matches!(&ast[2], Stmt::Expr(Expr::Group(_))));
+
+        let ast = parse_source("var f = (a) => { var b = a; return b; };").unwrap();
+        let Stmt::Var { init: Expr::Lambda(decl), .. } = &ast[0] else {
+            panic!("Expected a lambda, got {:?}", ast[0]);
+        };
+        assert_eq!(decl.body.len(), 2);
+
+        assert!(parse_source("(a, 1) => a;").is_err());
+        assert!(parse_source("while (true) f(() => { break; });").is_err());
+        assert!(parse_source("while (true) fun () { break; };").is_err());
+    }
+
+    #[test]
+    fn test_map_literal() {
+        let parse_source = |source: &str| parse(tokenize(source.to_string()).unwrap());
+
+        let ast = parse_source("var m = {\"a\": 1, 2: {}}; {} ({});").unwrap();
+        assert!(matches!(&ast[0], Stmt::Var { init: Expr::Map { entries, .. }, .. } if entries.len() == 2));
+        assert!(matches!(&ast[1], Stmt::Block(block) if block.stmts.is_empty()));
+        assert!(matches!(&ast[2], Stmt::Expr(Expr::Group(map)) if matches!(**map, Expr::Map { .. })));
+
+        // At the start of a statement, a brace opens a block.
+        assert!(parse_source("{\"a\": 1};").is_err());
+    }
+
+    #[test]
+    fn test_interpolation() {
+        let tokens = tokenize("\"${a}, ${ {\"b\": \"${c}\"}[\"b\"] }!\";".to_string()).unwrap();
+        let ast = parse(tokens).unwrap();
+
+        let Stmt::Expr(expr) = &ast[0] else {
+            panic!("Expected an expression, got {:?}", ast[0]);
+        };
+        assert_eq!(
+            expr.to_string(),
+            "(interpolate (var a) ,  (index (map (b (interpolate (var c)))) b) !)"
+        );
+    }
 }
@@ -1,4 +1,5 @@
-use crate::scanner::token_kind::TokenKind;
+use super::MAX_ARGS;
+use crate::scanner::{span::Span, token_kind::TokenKind};
 use std::fmt;
 
 #[derive(Clone, Copy, PartialEq)]
@@ -6,58 +7,106 @@ pub enum ParseError {
     ExpectedToken {
         expected: TokenKind,
         got: Option<TokenKind>,
-        line: usize,
+        span: Span,
     },
     ExpectedAnyToken {
-        line: usize,
+        span: Span,
     },
     ExpectedAnyLeftOperand {
         operator: TokenKind,
-        line: usize,
+        span: Span,
     },
-    EmptyExpression,
     NotParseable {
         token: TokenKind,
-        line: usize,
+        span: Span,
     },
     BadAssignmentTarget {
-        line: usize,
+        span: Span,
+    },
+    TooManyParameters {
+        span: Span,
+    },
+    TooManyArguments {
+        span: Span,
+    },
+    JumpOutsideLoop {
+        keyword: TokenKind,
+        span: Span,
+    },
+    UndefinedLabel {
+        span: Span,
+    },
+    BadLabelTarget {
+        span: Span,
+    },
+    TryWithoutHandler {
+        span: Span,
     },
 }
 
-impl fmt::Display for ParseError {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+impl ParseError {
+    /// Returns the span of source code the error is about.
+    pub fn span(&self) -> Span {
+        match self {
+            ParseError::ExpectedToken { span, .. }
+            | ParseError::ExpectedAnyToken { span }
+            | ParseError::ExpectedAnyLeftOperand { span, .. }
+            | ParseError::NotParseable { span, .. }
+            | ParseError::BadAssignmentTarget { span }
+            | ParseError::TooManyParameters { span }
+            | ParseError::TooManyArguments { span }
+            | ParseError::JumpOutsideLoop { span, .. }
+            | ParseError::UndefinedLabel { span }
+            | ParseError::BadLabelTarget { span }
+            | ParseError::TryWithoutHandler { span } => *span,
+        }
+    }
+
+    /// Returns a description of the error, without its location.
+    pub fn message(&self) -> String {
         match self {
             ParseError::ExpectedToken {
                 expected,
-                got,
-                line,
-            } => {
-                if let Some(got) = got {
-                    write!(f, "[line {line}] Expected `{:?}`, got `{:?}`", expected, got)
-                } else {
-                    write!(f, "[line {line}] Expected `{:?}`", expected)
-                }
+                got: Some(got),
+                ..
+            } => format!("Expected `{:?}`, got `{:?}`", expected, got),
+            ParseError::ExpectedToken {
+                expected,
+                got: None,
+                ..
+            } => format!("Expected `{:?}`", expected),
+            ParseError::ExpectedAnyToken { .. } => "Expected token".into(),
+            ParseError::ExpectedAnyLeftOperand { operator, .. } => {
+                format!("Expected left operand for `{:?}`", operator)
             }
-            ParseError::ExpectedAnyToken { line } => {
-                write!(f, "[line {line}] Expected token")
+            ParseError::NotParseable { token, .. } => {
+                format!("`{:?}` cannot be turned into an expression", token)
             }
-            ParseError::ExpectedAnyLeftOperand { operator, line } => {
-                write!(f, "[line {line}] Expected left operand for `{:?}`", operator)
+            ParseError::BadAssignmentTarget { .. } => "Invalid assignment target".into(),
+            ParseError::TooManyParameters { .. } => {
+                format!("Cannot have more than {MAX_ARGS} parameters")
             }
-            ParseError::EmptyExpression => {
-                write!(f, "Expression cannot be empty")
+            ParseError::TooManyArguments { .. } => {
+                format!("Cannot have more than {MAX_ARGS} arguments")
             }
-            ParseError::NotParseable { token, line } => {
-                write!(f, "[line {line}] `{:?}` cannot be turned into an expression", token)
+            ParseError::JumpOutsideLoop { keyword, .. } => {
+                format!("`{:?}` can only be used inside a loop", keyword)
             }
-            ParseError::BadAssignmentTarget { line } => {
-                write!(f, "[line {line}] Invalid assignment target")
+            ParseError::UndefinedLabel { .. } => "Label does not name an enclosing loop".into(),
+            ParseError::BadLabelTarget { .. } => "Only loops can be labeled".into(),
+            ParseError::TryWithoutHandler { .. } => {
+                "`try` must be followed by `catch`, `finally` or both".into()
             }
         }
     }
 }
 
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[line {}] {}", self.span().line, self.message())
+    }
+}
+
 impl fmt::Debug for ParseError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         fmt::Display::fmt(self, f)
@@ -1,3 +1,5 @@
+use std::rc::Rc;
+
 use super::expr::Expr;
 use crate::scanner::token::Token;
 
@@ -9,7 +11,7 @@ pub enum Stmt {
 		name: Token,
 		init: Expr,
 	},
-	Block(Vec<Stmt>),
+	Block(Block),
 	If {
 		condition: Expr,
 		then_branch: Box<Stmt>,
@@ -18,5 +20,63 @@ pub enum Stmt {
 	While {
 		condition: Expr,
 		body: Box<Stmt>,
+		// Ran after every iteration, even when it is `continue`d. Only used by desugared for loops.
+		increment: Option<Expr>,
+		label: Option<Token>,
+	},
+	Break {
+		keyword: Token,
+		label: Option<Token>,
+	},
+	Continue {
+		keyword: Token,
+		label: Option<Token>,
+	},
+	Function(Rc<FunctionDecl>),
+	Return {
+		keyword: Token,
+		value: Option<Expr>,
+	},
+	Class {
+		name: Token,
+		superclass: Option<Expr>,
+		methods: Vec<Rc<FunctionDecl>>,
+	},
+	Try {
+		keyword: Token,
+		body: Block,
+		// The variable the error is bound to, and the block handling it.
+		catch: Option<(Token, Block)>,
+		// Ran however the other blocks are left.
+		finally: Option<Block>,
+	},
+	Throw {
+		keyword: Token,
+		value: Expr,
 	},
 }
+
+/// Statements in a scope of their own.
+#[derive(Debug, Clone, Default)]
+pub struct Block {
+	pub stmts: Vec<Stmt>,
+	/// Filled in by the resolver. The variables of a block usually live in the environment of
+	/// the function it is in, and are dropped when the block is left. Blocks with variables
+	/// that closures capture get an environment of their own instead, which the closures keep.
+	pub own_env: bool,
+}
+
+impl Block {
+	pub fn new(stmts: Vec<Stmt>) -> Self {
+		Self { stmts, own_env: false }
+	}
+}
+
+/// The declaration of a named function or method, shared between the syntax tree and every
+/// runtime function created from it.
+#[derive(Debug, Clone)]
+pub struct FunctionDecl {
+	pub name: Token,
+	pub params: Vec<Token>,
+	pub body: Vec<Stmt>,
+}
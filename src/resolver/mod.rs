@@ -0,0 +1,590 @@
+pub mod resolve_error;
+
+use std::{collections::HashMap, rc::Rc};
+
+use crate::{
+    interpreter::class::INITIALIZER,
+    parser::{
+        expr::{Expr, Local},
+        stmt::{Block, FunctionDecl, Stmt},
+    },
+    scanner::token::Token,
+};
+
+use resolve_error::ResolveError::{self, *};
+
+#[derive(Clone, Copy, PartialEq)]
+enum FunctionKind {
+    None,
+    Function,
+    Method,
+    Initializer,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum ClassKind {
+    None,
+    Class,
+    Subclass,
+}
+
+/// Binds every local variable usage to the environment and slot it was declared in, by filling
+/// in the `local` of variable-like expressions, and marks the blocks needing an environment.
+/// Also reports mistakes that can be caught before running, such as `return` outside of a function.
+pub fn resolve(stmts: &mut [Stmt]) -> Result<(), ResolveError> {
+    // Whether a block needs an environment is only known once the closures in it have been
+    // seen, and that moves the variables used before them. So the first pass marks the blocks,
+    // and the second one resolves every variable again knowing about all of them.
+    for _ in 0..2 {
+        let mut resolver = Resolver {
+            scopes: Vec::new(),
+            function: FunctionKind::None,
+            class: ClassKind::None,
+        };
+
+        for stmt in stmts.iter_mut() {
+            resolver.stmt(stmt)?;
+        }
+    }
+
+    Ok(())
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum ScopeKind {
+    // The parameters and body of a function, which every call gets an environment for.
+    Function,
+    // Where `this` or `super` is bound, each in an environment of its own.
+    Class,
+    // A block, which only gets an environment if it needs one.
+    Block { own_env: bool },
+}
+
+struct Scope {
+    kind: ScopeKind,
+    variables: HashMap<String, Variable>,
+    // The slot the next variable declared here takes in the environment the scope is in.
+    next_slot: usize,
+    // Whether a function nested in the scope uses one of its variables.
+    captured: bool,
+}
+
+impl Scope {
+    fn own_env(&self) -> bool {
+        !matches!(self.kind, ScopeKind::Block { own_env: false })
+    }
+}
+
+struct Variable {
+    slot: usize,
+    // Whether the variable has finished being initialized.
+    defined: bool,
+}
+
+struct Resolver {
+    // Local scopes only, innermost last. Globals are not tracked, so anything that
+    // cannot be found here is assumed to be global.
+    scopes: Vec<Scope>,
+    function: FunctionKind,
+    class: ClassKind,
+}
+
+impl Resolver {
+    fn stmt(&mut self, stmt: &mut Stmt) -> Result<(), ResolveError> {
+        match stmt {
+            Stmt::Expr(expr) | Stmt::Print(expr) => self.expr(expr)?,
+
+            Stmt::Var { name, init } => {
+                // Declared and defined separately so that the initializer can't see the
+                // variable it is initializing, such as: var a = a;
+                self.declare(name)?;
+                self.expr(init)?;
+                self.define(name);
+            }
+
+            Stmt::Block(block) => self.block(block, None)?,
+
+            Stmt::If {
+                condition,
+                then_branch,
+                else_branch,
+            } => {
+                self.expr(condition)?;
+                self.stmt(then_branch)?;
+                if let Some(else_branch) = else_branch {
+                    self.stmt(else_branch)?;
+                }
+            }
+
+            Stmt::While {
+                condition,
+                body,
+                increment,
+                ..
+            } => {
+                self.expr(condition)?;
+                self.stmt(body)?;
+                if let Some(increment) = increment {
+                    self.expr(increment)?;
+                }
+            }
+
+            Stmt::Break { .. } | Stmt::Continue { .. } => (),
+
+            Stmt::Try {
+                body,
+                catch,
+                finally,
+                ..
+            } => {
+                self.block(body, None)?;
+
+                // The error is bound in the same scope as the catch block's own variables.
+                if let Some((name, catch)) = catch {
+                    self.block(catch, Some(name))?;
+                }
+
+                if let Some(finally) = finally {
+                    self.block(finally, None)?;
+                }
+            }
+
+            Stmt::Throw { value, .. } => self.expr(value)?,
+
+            Stmt::Function(decl) => {
+                // Defined eagerly so that the function can refer to itself recursively.
+                self.declare(&decl.name)?;
+                self.define(&decl.name);
+                self.function(decl, FunctionKind::Function)?;
+            }
+
+            Stmt::Return { keyword, value } => {
+                if self.function == FunctionKind::None {
+                    return Err(ReturnOutsideFunction {
+                        keyword: keyword.clone(),
+                    });
+                }
+
+                if let Some(value) = value {
+                    if self.function == FunctionKind::Initializer {
+                        return Err(ReturnFromInitializer {
+                            keyword: keyword.clone(),
+                        });
+                    }
+
+                    self.expr(value)?;
+                }
+            }
+
+            Stmt::Class {
+                name,
+                superclass,
+                methods,
+            } => {
+                let enclosing_class = self.class;
+                self.class = ClassKind::Class;
+
+                self.declare(name)?;
+                self.define(name);
+
+                if let Some(superclass) = superclass {
+                    if let Expr::Variable {
+                        name: super_name, ..
+                    } = superclass
+                    {
+                        if super_name.lexeme == name.lexeme {
+                            return Err(InheritsFromItself {
+                                name: super_name.clone(),
+                            });
+                        }
+                    }
+
+                    self.class = ClassKind::Subclass;
+                    self.expr(superclass)?;
+
+                    // Mirrors the environment the interpreter creates to hold `super`.
+                    self.begin_scope(ScopeKind::Class);
+                    self.define_implicit("super");
+                }
+
+                // Mirrors the environment methods get when bound to an instance.
+                self.begin_scope(ScopeKind::Class);
+                self.define_implicit("this");
+
+                for method in methods {
+                    let kind = if method.name.lexeme == INITIALIZER {
+                        FunctionKind::Initializer
+                    } else {
+                        FunctionKind::Method
+                    };
+
+                    self.function(method, kind)?;
+                }
+
+                self.end_scope();
+
+                if superclass.is_some() {
+                    self.end_scope();
+                }
+
+                self.class = enclosing_class;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn expr(&mut self, expr: &mut Expr) -> Result<(), ResolveError> {
+        match expr {
+            Expr::Literal(_) => (),
+
+            Expr::Unary(_, r) => self.expr(r)?,
+
+            Expr::Binary(l, _, r) | Expr::Logical(l, _, r) => {
+                self.expr(l)?;
+                self.expr(r)?;
+            }
+
+            Expr::Group(expr) => self.expr(expr)?,
+
+            Expr::Update { target, value, .. } => {
+                self.expr(target)?;
+                self.expr(value)?;
+            }
+
+            Expr::Interpolation(parts) => {
+                for part in parts {
+                    self.expr(part)?;
+                }
+            }
+
+            Expr::Ternary(expr, if_, else_) => {
+                self.expr(expr)?;
+                self.expr(if_)?;
+                self.expr(else_)?;
+            }
+
+            Expr::Variable { name, local } => {
+                let in_own_initializer = self
+                    .scopes
+                    .last()
+                    .and_then(|scope| scope.variables.get(&name.lexeme))
+                    .is_some_and(|variable| !variable.defined);
+
+                if in_own_initializer {
+                    return Err(ReadInOwnInitializer { name: name.clone() });
+                }
+
+                *local = self.local(&name.lexeme);
+            }
+
+            Expr::Assign { name, value, local } => {
+                self.expr(value)?;
+                *local = self.local(&name.lexeme);
+            }
+
+            Expr::Call { callee, args, .. } => {
+                self.expr(callee)?;
+                for arg in args {
+                    self.expr(arg)?;
+                }
+            }
+
+            // Properties are looked up dynamically, so only the object needs resolving.
+            Expr::Get { object, .. } => self.expr(object)?,
+
+            Expr::Set { object, value, .. } => {
+                self.expr(value)?;
+                self.expr(object)?;
+            }
+
+            Expr::Lambda(decl) => self.function(decl, FunctionKind::Function)?,
+
+            Expr::List { items, .. } => {
+                for item in items {
+                    self.expr(item)?;
+                }
+            }
+
+            Expr::Map { entries, .. } => {
+                for (key, value) in entries {
+                    self.expr(key)?;
+                    self.expr(value)?;
+                }
+            }
+
+            Expr::Index { object, index, .. } => {
+                self.expr(object)?;
+                self.expr(index)?;
+            }
+
+            Expr::SetIndex {
+                object,
+                index,
+                value,
+                ..
+            } => {
+                self.expr(object)?;
+                self.expr(index)?;
+                self.expr(value)?;
+            }
+
+            Expr::This { keyword, local } => {
+                if self.class == ClassKind::None {
+                    return Err(ThisOutsideClass {
+                        keyword: keyword.clone(),
+                    });
+                }
+
+                *local = self.local("this");
+            }
+
+            Expr::Super { keyword, local, .. } => match self.class {
+                ClassKind::None => {
+                    return Err(SuperOutsideClass {
+                        keyword: keyword.clone(),
+                    })
+                }
+                ClassKind::Class => {
+                    return Err(SuperWithoutSuperclass {
+                        keyword: keyword.clone(),
+                    })
+                }
+                ClassKind::Subclass => *local = self.local("super"),
+            },
+        }
+
+        Ok(())
+    }
+
+    fn function(
+        &mut self,
+        decl: &mut Rc<FunctionDecl>,
+        kind: FunctionKind,
+    ) -> Result<(), ResolveError> {
+        let decl = Rc::get_mut(decl)
+            .expect("Function declarations should not be shared before being resolved");
+
+        let enclosing_function = self.function;
+        self.function = kind;
+
+        self.begin_scope(ScopeKind::Function);
+
+        for param in &decl.params {
+            self.declare(param)?;
+            self.define(param);
+        }
+
+        // The body shares the scope of the parameters, just like at runtime.
+        for stmt in &mut decl.body {
+            self.stmt(stmt)?;
+        }
+
+        self.end_scope();
+
+        self.function = enclosing_function;
+
+        Ok(())
+    }
+
+    // Returns where the innermost local called `name` lives, or `None` if it is not a local.
+    // The scope declaring it is marked as captured if it is used from a nested function.
+    fn local(&mut self, name: &str) -> Option<Local> {
+        let mut depth = 0;
+        let mut in_function = false;
+
+        for scope in self.scopes.iter_mut().rev() {
+            if let Some(variable) = scope.variables.get(name) {
+                let slot = variable.slot;
+                scope.captured |= in_function;
+                return Some(Local { depth, slot });
+            }
+
+            // Only scopes with an environment of their own are a step up at runtime.
+            if scope.own_env() {
+                depth += 1;
+            }
+            in_function |= scope.kind == ScopeKind::Function;
+        }
+
+        None
+    }
+
+    // Resolves a block, with `binding` declared before its statements.
+    fn block(&mut self, block: &mut Block, binding: Option<&Token>) -> Result<(), ResolveError> {
+        // Blocks outside of functions have no environment to share but the global one.
+        let own_env = block.own_env || self.scopes.is_empty();
+
+        self.begin_scope(ScopeKind::Block { own_env });
+        if let Some(name) = binding {
+            self.declare(name)?;
+            self.define(name);
+        }
+        for stmt in &mut block.stmts {
+            self.stmt(stmt)?;
+        }
+        let scope = self.end_scope();
+
+        // A closure can outlive the block, or a pass through a loop body, whose variables it
+        // captured, so they can't be dropped from the shared environment once the block ends.
+        block.own_env = own_env || scope.captured;
+
+        Ok(())
+    }
+
+    fn begin_scope(&mut self, kind: ScopeKind) {
+        // Blocks sharing an environment number their variables after the ones already in it,
+        // and hand their slots back when they end.
+        let next_slot = match (kind, self.scopes.last()) {
+            (ScopeKind::Block { own_env: false }, Some(enclosing)) => enclosing.next_slot,
+            _ => 0,
+        };
+
+        self.scopes.push(Scope {
+            kind,
+            variables: HashMap::new(),
+            next_slot,
+            captured: false,
+        });
+    }
+
+    fn end_scope(&mut self) -> Scope {
+        self.scopes.pop().expect("Scopes should be balanced")
+    }
+
+    fn declare(&mut self, name: &Token) -> Result<(), ResolveError> {
+        if let Some(scope) = self.scopes.last_mut() {
+            if scope.variables.contains_key(&name.lexeme) {
+                return Err(AlreadyDeclared { name: name.clone() });
+            }
+
+            // Slots are handed out in declaration order, which is also the order in which
+            // the interpreter defines variables.
+            let slot = scope.next_slot;
+            scope.next_slot += 1;
+            scope.variables.insert(
+                name.lexeme.clone(),
+                Variable {
+                    slot,
+                    defined: false,
+                },
+            );
+        }
+
+        Ok(())
+    }
+
+    fn define(&mut self, name: &Token) {
+        if let Some(variable) = self
+            .scopes
+            .last_mut()
+            .and_then(|scope| scope.variables.get_mut(&name.lexeme))
+        {
+            variable.defined = true;
+        }
+    }
+
+    // Defines a variable that has no declaration in the source, such as `this`.
+    fn define_implicit(&mut self, name: &str) {
+        if let Some(scope) = self.scopes.last_mut() {
+            let slot = scope.next_slot;
+            scope.next_slot += 1;
+            scope.variables.insert(
+                name.into(),
+                Variable {
+                    slot,
+                    defined: true,
+                },
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::resolve;
+    use crate::{
+        parser::{
+            expr::{Expr, Local},
+            parse,
+            stmt::Stmt,
+        },
+        scanner::tokenize,
+    };
+
+    fn resolve_source(source: &str) -> Result<Vec<Stmt>, String> {
+        let mut stmts = parse(tokenize(source.into()).unwrap()).unwrap();
+        resolve(&mut stmts).map_err(|e| e.to_string())?;
+        Ok(stmts)
+    }
+
+    #[test]
+    fn test_depths() {
+        let stmts = resolve_source("var a; { var z; var b; { print a; print b; } }").unwrap();
+
+        // The outer block has no function to share an environment with, the inner one uses its.
+        let Stmt::Block(outer) = &stmts[1] else {
+            panic!()
+        };
+        let Stmt::Block(inner) = &outer.stmts[2] else {
+            panic!()
+        };
+        assert!(outer.own_env && !inner.own_env);
+        assert!(matches!(
+            inner.stmts[0],
+            Stmt::Print(Expr::Variable { local: None, .. })
+        ));
+        assert!(matches!(
+            inner.stmts[1],
+            Stmt::Print(Expr::Variable {
+                local: Some(Local { depth: 0, slot: 1 }),
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn test_captured_blocks() {
+        let source = "fun f(x) { { var a; print a; } { print x; var b; fun g() { return b; } } }";
+        let stmts = resolve_source(source).unwrap();
+
+        let Stmt::Function(decl) = &stmts[0] else {
+            panic!()
+        };
+        let (Stmt::Block(shared), Stmt::Block(captured)) = (&decl.body[0], &decl.body[1]) else {
+            panic!()
+        };
+
+        // Only the block with a variable used by `g` needs an environment, which moves `x`
+        // one step further away even though it is used before `g` is declared.
+        assert!(!shared.own_env && captured.own_env);
+        assert!(matches!(
+            shared.stmts[1],
+            Stmt::Print(Expr::Variable {
+                local: Some(Local { depth: 0, slot: 1 }),
+                ..
+            })
+        ));
+        assert!(matches!(
+            captured.stmts[0],
+            Stmt::Print(Expr::Variable {
+                local: Some(Local { depth: 1, slot: 0 }),
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn test_errors() {
+        assert!(resolve_source("{ var a = a; }").is_err());
+        assert!(resolve_source("{ var a; var a; }").is_err());
+        assert!(resolve_source("return 1;").is_err());
+        assert!(resolve_source("print this;").is_err());
+        assert!(resolve_source("class A { init() { return 1; } }").is_err());
+        assert!(resolve_source("class A { f() { super.f(); } }").is_err());
+        assert!(resolve_source("class A < A {}").is_err());
+
+        // Globals can be redeclared, unlike locals.
+        assert!(resolve_source("var a; var a;").is_ok());
+    }
+}
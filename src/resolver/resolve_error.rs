@@ -0,0 +1,74 @@
+use std::fmt;
+
+use crate::scanner::{span::Span, token::Token};
+
+#[derive(Clone)]
+pub enum ResolveError {
+    ReadInOwnInitializer { name: Token },
+    AlreadyDeclared { name: Token },
+    ReturnOutsideFunction { keyword: Token },
+    ReturnFromInitializer { keyword: Token },
+    ThisOutsideClass { keyword: Token },
+    SuperOutsideClass { keyword: Token },
+    SuperWithoutSuperclass { keyword: Token },
+    InheritsFromItself { name: Token },
+}
+
+impl ResolveError {
+    /// Returns the span of source code the error is about.
+    pub fn span(&self) -> Span {
+        match self {
+            ResolveError::ReadInOwnInitializer { name }
+            | ResolveError::AlreadyDeclared { name }
+            | ResolveError::InheritsFromItself { name } => name.span,
+
+            ResolveError::ReturnOutsideFunction { keyword }
+            | ResolveError::ReturnFromInitializer { keyword }
+            | ResolveError::ThisOutsideClass { keyword }
+            | ResolveError::SuperOutsideClass { keyword }
+            | ResolveError::SuperWithoutSuperclass { keyword } => keyword.span,
+        }
+    }
+
+    /// Returns a description of the error, without its location.
+    pub fn message(&self) -> String {
+        match self {
+            ResolveError::ReadInOwnInitializer { name } => format!(
+                "Cannot read local variable `{}` in its own initializer",
+                name.lexeme
+            ),
+            ResolveError::AlreadyDeclared { name } => format!(
+                "Variable `{}` is already declared in this scope",
+                name.lexeme
+            ),
+            ResolveError::ReturnOutsideFunction { .. } => {
+                "Cannot return from top-level code".into()
+            }
+            ResolveError::ReturnFromInitializer { .. } => {
+                "Cannot return a value from an initializer".into()
+            }
+            ResolveError::ThisOutsideClass { .. } => "Cannot use `this` outside of a class".into(),
+            ResolveError::SuperOutsideClass { .. } => {
+                "Cannot use `super` outside of a class".into()
+            }
+            ResolveError::SuperWithoutSuperclass { .. } => {
+                "Cannot use `super` in a class with no superclass".into()
+            }
+            ResolveError::InheritsFromItself { name } => {
+                format!("Class `{}` cannot inherit from itself", name.lexeme)
+            }
+        }
+    }
+}
+
+impl fmt::Display for ResolveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[line {}] {}", self.span().line, self.message())
+    }
+}
+
+impl fmt::Debug for ResolveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
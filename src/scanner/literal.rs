@@ -1,32 +1,26 @@
 use std::fmt;
 
-use crate::{interpreter::runtime_error::RuntimeError, scanner::token_kind::TokenKind};
-
 #[derive(Clone, Debug, PartialEq)]
 pub enum Literal {
+    Int(i64),
     Number(f64),
     String(String),
     Bool(bool),
     Nil,
 }
 
-impl Literal {
-    /// Returns `false` for `Nil` and `Bool(false)`.
-    pub fn is_truthy(&self) -> bool {
-        match self {
-            Literal::Nil => false,
-            Literal::Bool(b) => *b,
-            _ => true,
-        }
-    }
-}
-
 impl From<f64> for Literal {
     fn from(n: f64) -> Self {
         Literal::Number(n)
     }
 }
 
+impl From<i64> for Literal {
+    fn from(n: i64) -> Self {
+        Literal::Int(n)
+    }
+}
+
 impl From<&str> for Literal {
     fn from(s: &str) -> Self {
         Literal::String(s.into())
@@ -42,6 +36,7 @@ impl From<bool> for Literal {
 impl fmt::Display for Literal {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
+            Literal::Int(n) => write!(f, "{}", n),
             Literal::Number(n) => write!(f, "{}", n),
             Literal::String(s) => write!(f, "{}", s),
             Literal::Bool(b) => write!(f, "{}", b),
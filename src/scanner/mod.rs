@@ -1,11 +1,13 @@
 pub mod literal;
 pub mod scan_error;
+pub mod span;
 pub mod token;
 pub mod token_kind;
 
 use self::{
     literal::Literal,
     scan_error::ScanError::{self, *},
+    span::Span,
     token::Token,
     token_kind::TokenKind as TK,
 };
@@ -23,18 +25,65 @@ fn is_identifier_continue(c: char) -> bool {
     c.is_ascii_alphanumeric() || c == '_'
 }
 
+/// Maps source code into tokens, always ending with an `Eof` token that marks
+/// where the source ends.
+/// Interpolated strings are split into segments around the tokens of the expressions they
+/// embed, so "a${b}c" becomes `InterpolationStart`, `Identifier`, `InterpolationEnd`.
 pub fn tokenize(source: String) -> Result<Vec<Token>, ScanError> {
     let mut cursor = StringCursor::new(source);
     let mut tokens: Vec<Token> = Vec::new();
 
-    loop {
-        match cursor.eat_token() {
-            Ok(Some(token)) => tokens.push(token),
-            Ok(None) => break,
-            Err(e) => return Err(e),
+    // The interpolations being scanned, innermost last, as the opening quote of their string
+    // and the amount of braces opened inside of them so far.
+    let mut interpolations: Vec<(Span, usize)> = Vec::new();
+
+    while let Some(token) = cursor.eat_token()? {
+        match token.kind {
+            TK::InterpolationStart => {
+                let opening = Span {
+                    end: token.span.start + 1,
+                    ..token.span
+                };
+                interpolations.push((opening, 0));
+            }
+
+            TK::LeftBrace => {
+                if let Some((_, depth)) = interpolations.last_mut() {
+                    *depth += 1;
+                }
+            }
+
+            TK::RightBrace => match interpolations.last_mut() {
+                // The brace closes the interpolation, so the string carries on right after it.
+                Some((opening, 0)) => {
+                    let segment = cursor.eat_string_segment(*opening, true)?;
+                    if segment.kind == TK::InterpolationEnd {
+                        interpolations.pop();
+                    }
+
+                    tokens.push(segment);
+                    continue;
+                }
+                Some((_, depth)) => *depth -= 1,
+                None => (),
+            },
+
+            _ => (),
         }
+
+        tokens.push(token);
+    }
+
+    if let Some((opening, _)) = interpolations.pop() {
+        return Err(UnterminatedString { span: opening });
     }
 
+    tokens.push(Token::symbol(
+        TK::Eof,
+        String::new(),
+        cursor.span_since_checkpoint(),
+    ));
+
     Ok(tokens)
 }
 
@@ -56,14 +105,18 @@ impl StringCursor {
             ')' => TK::RightParenthesis,
             '{' => TK::LeftBrace,
             '}' => TK::RightBrace,
+            '[' => TK::LeftBracket,
+            ']' => TK::RightBracket,
             ',' => TK::Comma,
             '.' => TK::Dot,
-            '+' => TK::Plus,
-            '-' => TK::Minus,
             ';' => TK::Semicolon,
-            '*' => TK::Star,
             '?' => TK::Question,
             ':' => TK::Colon,
+            '%' => TK::Percent,
+            '&' => TK::Ampersand,
+            '|' => TK::Pipe,
+            '^' => TK::Caret,
+            '~' => TK::Tilde,
 
             // Double lexeme.
             '!' => {
@@ -75,38 +128,89 @@ impl StringCursor {
                 }
             }
 
-            '=' => {
-                if self.current() == '=' {
+            '=' => match self.current() {
+                '=' => {
                     self.eat();
                     TK::EqualEqual
-                } else {
-                    TK::Equal
                 }
-            }
+                '>' => {
+                    self.eat();
+                    TK::Arrow
+                }
+                _ => TK::Equal,
+            },
 
-            '<' => {
-                if self.current() == '=' {
+            '+' => match self.current() {
+                '=' => {
+                    self.eat();
+                    TK::PlusEqual
+                }
+                '+' => {
+                    self.eat();
+                    TK::PlusPlus
+                }
+                _ => TK::Plus,
+            },
+
+            '-' => match self.current() {
+                '=' => {
+                    self.eat();
+                    TK::MinusEqual
+                }
+                '-' => {
+                    self.eat();
+                    TK::MinusMinus
+                }
+                _ => TK::Minus,
+            },
+
+            '*' => match self.current() {
+                '=' => {
+                    self.eat();
+                    TK::StarEqual
+                }
+                '*' => {
+                    self.eat();
+                    TK::StarStar
+                }
+                _ => TK::Star,
+            },
+
+            '<' => match self.current() {
+                '=' => {
                     self.eat();
                     TK::LessEqual
-                } else {
-                    TK::Less
                 }
-            }
+                '<' => {
+                    self.eat();
+                    TK::LessLess
+                }
+                _ => TK::Less,
+            },
 
-            '>' => {
-                if self.current() == '=' {
+            '>' => match self.current() {
+                '=' => {
                     self.eat();
                     TK::GreaterEqual
-                } else {
-                    TK::Greater
                 }
-            }
+                '>' => {
+                    self.eat();
+                    TK::GreaterGreater
+                }
+                _ => TK::Greater,
+            },
 
             // Multiple lexemes.
             '/' => {
                 if self.current() == '/' {
                     self.skip_line_comment();
                     return self.eat_token();
+                } else if self.current() == '*' {
+                    self.skip_block_comment()?;
+                    return self.eat_token();
+                } else if self.current() == '=' {
+                    self.eat();
+                    TK::SlashEqual
                 } else {
                     TK::Slash
                 }
@@ -114,6 +218,9 @@ impl StringCursor {
 
             '"' => return self.eat_string_token(),
 
+            // r"..." and r#"..."# are raw strings rather than identifiers.
+            'r' if matches!(self.current(), '"' | '#') => return self.eat_raw_string_token(),
+
             c if c.is_ascii_digit() => return self.eat_number_token(),
 
             c if is_identifier_start(c) => return Ok(self.eat_identifier_token()),
@@ -124,7 +231,7 @@ impl StringCursor {
             c => {
                 return Err(UnexpectedChar {
                     ch: c,
-                    line: self.line(),
+                    span: self.span_since_checkpoint(),
                 })
             }
         };
@@ -132,7 +239,7 @@ impl StringCursor {
         Ok(Some(Token::symbol(
             symbol_kind,
             self.string_since_checkpoint(),
-            self.line(),
+            self.span_since_checkpoint(),
         )))
     }
 
@@ -143,52 +250,267 @@ impl StringCursor {
             "Should be called after eating the opening quote"
         );
 
-        self.eat_while(|c| c != '"' && c != EOF);
+        // The opening quote, which is what gets pointed at if the string never ends.
+        let opening = self.span_since_checkpoint();
+        self.eat_string_segment(opening, false).map(Some)
+    }
 
-        if self.is_eof() {
-            return Err(UnterminatedString { line: self.line() });
+    // Eats a string up to its closing quote, or up to the `${` of an interpolation.
+    // `continued` is whether the string carries on after an interpolation, rather than
+    // after its opening quote.
+    fn eat_string_segment(&mut self, opening: Span, continued: bool) -> Result<Token, ScanError> {
+        let mut literal = String::new();
+
+        // Strings can span several lines, the newlines being part of the string.
+        let interpolates = loop {
+            if self.is_eof() {
+                return Err(UnterminatedString { span: opening });
+            }
+
+            let start = self.span_here();
+            match self.eat() {
+                '"' => break false,
+                '$' if self.current() == '{' => {
+                    self.eat();
+                    break true;
+                }
+                // A backslash right before the end is left for the check above.
+                '\\' if !self.is_eof() => literal.push(self.eat_escape(start)?),
+                c => literal.push(c),
+            }
+        };
+
+        let kind = match (continued, interpolates) {
+            (false, false) => TK::String,
+            (false, true) => TK::InterpolationStart,
+            (true, true) => TK::InterpolationMid,
+            (true, false) => TK::InterpolationEnd,
+        };
+
+        Ok(Token::new(
+            kind,
+            self.string_since_checkpoint(),
+            literal.as_str().into(),
+            self.span_since_checkpoint(),
+        ))
+    }
+
+    // Eats what follows a backslash in a string and returns the character it stands for.
+    // `start` is where the backslash is.
+    fn eat_escape(&mut self, start: Span) -> Result<char, ScanError> {
+        let escaped = match self.eat() {
+            'n' => '\n',
+            't' => '\t',
+            'r' => '\r',
+            '\\' => '\\',
+            '"' => '"',
+            '$' => '$',
+            'u' => return self.eat_unicode_escape(start),
+            _ => {
+                return Err(InvalidEscape {
+                    span: self.span_since(start),
+                })
+            }
+        };
+
+        Ok(escaped)
+    }
+
+    // Eats the `{XXXX}` part of a `\u{XXXX}` escape, which holds the hexadecimal code point
+    // of the character in 1 to 6 digits, like in Rust.
+    fn eat_unicode_escape(&mut self, start: Span) -> Result<char, ScanError> {
+        let mut code = None;
+
+        if self.current() == '{' {
+            self.eat();
+            let digits: String = self
+                .eat_while(|c| c.is_ascii_hexdigit())
+                .into_iter()
+                .collect();
+
+            if self.current() == '}' && digits.len() <= 6 {
+                self.eat();
+                code = u32::from_str_radix(&digits, 16).ok();
+            }
+        }
+
+        code.and_then(char::from_u32).ok_or(InvalidEscape {
+            span: self.span_since(start),
+        })
+    }
+
+    // Raw strings have no escapes, so that backslashes can be written as is.
+    // Any amount of `#` can surround the quotes, in which case the string only ends at a quote
+    // followed by as many `#`, which allows writing quotes inside: r#"say "hi""#.
+    fn eat_raw_string_token(&mut self) -> Result<Option<Token>, ScanError> {
+        assert_eq!(self.prev(), 'r', "Should be called after eating the `r`");
+
+        let hashes = self.eat_while(|c| c == '#').len();
+
+        if self.current() != '"' {
+            let start = self.span_here();
+            return Err(UnexpectedChar {
+                ch: self.eat(),
+                span: self.span_since(start),
+            });
         }
 
-        // The closing quote.
         self.eat();
+        let opening = self.span_since_checkpoint();
+        let mut literal = String::new();
+
+        loop {
+            if self.is_eof() {
+                return Err(UnterminatedString { span: opening });
+            }
+
+            let c = self.eat();
+            if c != '"' {
+                literal.push(c);
+                continue;
+            }
+
+            let mut closing = 0;
+            while closing < hashes && self.current() == '#' {
+                self.eat();
+                closing += 1;
+            }
+
+            if closing == hashes {
+                break;
+            }
+
+            // Not the end after all, so the quote and hashes are part of the string.
+            literal.push('"');
+            literal.extend(std::iter::repeat_n('#', closing));
+        }
 
-        let lexeme = &self.string_since_checkpoint();
-        let literal = lexeme.trim_matches('"');
         Ok(Some(Token::new(
             TK::String,
-            lexeme.into(),
-            literal.into(),
-            self.line(),
+            self.string_since_checkpoint(),
+            literal.as_str().into(),
+            self.span_since_checkpoint(),
         )))
     }
 
+    // Numbers are integers, in decimal (42), hexadecimal (0xFF) or binary (0b1010), or floats
+    // with a fraction, an exponent or both (6.02e23). Digits can be separated by underscores.
     fn eat_number_token(&mut self) -> Result<Option<Token>, ScanError> {
         assert!(
             self.prev().is_ascii_digit(),
             "Should be called after eating the first digit"
         );
 
-        self.eat_while(|c| c.is_ascii_digit());
-
-        if self.current() == '.' {
-            if !self.next().is_ascii_digit() {
-                return Err(ExpectedDigitAfterDot { line: self.line() });
-            }
+        let radix = match (self.prev(), self.current()) {
+            ('0', 'x') => 16,
+            ('0', 'b') => 2,
+            _ => 10,
+        };
 
+        let literal = if radix == 10 {
+            self.eat_decimal()?
+        } else {
+            // The radix prefix.
             self.eat();
-            self.eat_while(|c| c.is_ascii_digit());
+            let digits = self.eat_digits(radix)?;
+            i64::from_str_radix(&digits, radix)
+                .map(Literal::Int)
+                .map_err(|_| IntegerTooLarge {
+                    span: self.span_since_checkpoint(),
+                })?
+        };
+
+        // Letters and digits right after a number would otherwise silently start another token,
+        // like the `2` in 0b12.
+        if is_identifier_continue(self.current()) {
+            self.eat_while(is_identifier_continue);
+            return Err(self.malformed_number());
         }
 
-        let lexeme = &self.string_since_checkpoint();
-        let literal = lexeme.parse::<f64>().expect("Should be a valid number");
         Ok(Some(Token::new(
             TK::Number,
-            lexeme.into(),
-            literal.into(),
-            self.line(),
+            self.string_since_checkpoint(),
+            literal,
+            self.span_since_checkpoint(),
         )))
     }
 
+    // Eats the rest of a decimal number whose first digit was just eaten.
+    fn eat_decimal(&mut self) -> Result<Literal, ScanError> {
+        let mut number = self.prev().to_string();
+        let mut is_float = false;
+
+        if matches!(self.current(), '0'..='9' | '_') {
+            number += &self.eat_separated_digits(10)?;
+        }
+
+        if self.current() == '.' {
+            if !self.next().is_ascii_digit() {
+                // Points at the dot.
+                self.eat();
+                return Err(ExpectedDigitAfterDot {
+                    span: self.span_since_checkpoint(),
+                });
+            }
+
+            is_float = true;
+            number.push(self.eat());
+            number += &self.eat_digits(10)?;
+        }
+
+        if matches!(self.current(), 'e' | 'E') {
+            is_float = true;
+            number.push(self.eat());
+
+            if matches!(self.current(), '+' | '-') {
+                number.push(self.eat());
+            }
+
+            number += &self.eat_digits(10)?;
+        }
+
+        if is_float {
+            number
+                .parse()
+                .map(Literal::Number)
+                .map_err(|_| self.malformed_number())
+        } else {
+            number.parse().map(Literal::Int).map_err(|_| IntegerTooLarge {
+                span: self.span_since_checkpoint(),
+            })
+        }
+    }
+
+    // Eats a run of digits in `radix` that may be separated by underscores, and returns the
+    // digits without the underscores. There must be at least one digit.
+    fn eat_digits(&mut self, radix: u32) -> Result<String, ScanError> {
+        if !self.current().is_digit(radix) {
+            self.eat_while(is_identifier_continue);
+            return Err(self.malformed_number());
+        }
+
+        self.eat_separated_digits(radix)
+    }
+
+    // Like `eat_digits`, but the run may start with an underscore, for when the digits carry
+    // on after one that was already eaten.
+    fn eat_separated_digits(&mut self, radix: u32) -> Result<String, ScanError> {
+        let eaten = self.eat_while(|c| c.is_digit(radix) || c == '_');
+
+        // Underscores only go between digits.
+        if self.prev() == '_' {
+            return Err(self.malformed_number());
+        }
+
+        Ok(eaten.into_iter().filter(|&c| c != '_').collect())
+    }
+
+    fn malformed_number(&self) -> ScanError {
+        MalformedNumber {
+            span: self.span_since_checkpoint(),
+        }
+    }
+
     fn eat_identifier_token(&mut self) -> Option<Token> {
         assert!(
             is_identifier_start(self.prev()),
@@ -208,20 +530,55 @@ impl StringCursor {
         };
 
         if let Some(l) = lit {
-            Some(Token::new(kind, lexeme.into(), l, self.line()))
+            Some(Token::new(kind, lexeme.into(), l, self.span_since_checkpoint()))
         } else {
-            Some(Token::symbol(kind, lexeme.into(), self.line()))
+            Some(Token::symbol(kind, lexeme.into(), self.span_since_checkpoint()))
         }
     }
 
     fn skip_line_comment(&mut self) {
         self.eat_while(|c| c != '\n' && c != EOF);
     }
+
+    // Block comments nest, so that commenting out code that has comments of its own works:
+    // /* a /* b */ c */ is a single comment.
+    fn skip_block_comment(&mut self) -> Result<(), ScanError> {
+        assert_eq!(
+            self.prev(),
+            '/',
+            "Should be called after eating the opening slash"
+        );
+
+        self.eat();
+        // The outermost opening delimiter, which is what gets pointed at if it is never closed.
+        let opening = self.span_since_checkpoint();
+        let mut depth = 1;
+
+        while depth > 0 {
+            match (self.eat(), self.current()) {
+                (EOF, _) => return Err(UnterminatedComment { span: opening }),
+                ('/', '*') => {
+                    self.eat();
+                    depth += 1;
+                }
+                ('*', '/') => {
+                    self.eat();
+                    depth -= 1;
+                }
+                _ => (),
+            }
+        }
+
+        Ok(())
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::tokenize;
+    use super::{
+        literal::Literal, scan_error::ScanError::*, span::Span, token_kind::TokenKind as TK,
+        tokenize,
+    };
     use std::{fs, path::Path};
 
     #[test]
@@ -230,4 +587,89 @@ mod tests {
         let tokens = tokenize(source).unwrap();
         println!("{:#?}", tokens);
     }
+
+    #[test]
+    fn test_spans() {
+        let tokens = tokenize("var x =\n  \"ab\"; ".to_string()).unwrap();
+
+        let string = &tokens[3];
+        assert_eq!((string.span.start, string.span.end), (10, 14));
+        assert_eq!((string.span.line, string.span.column), (2, 3));
+
+        let eof = tokens.last().unwrap();
+        assert_eq!(eof.kind, TK::Eof);
+        assert_eq!(eof.span.start, 16);
+    }
+
+    #[test]
+    fn test_escapes() {
+        let string = |source: &str| tokenize(source.to_string()).unwrap()[0].literal.clone();
+
+        assert_eq!(
+            string(r#""a\tb\n\\\"\u{48}\u{1F600}""#),
+            Some(Literal::from("a\tb\n\\\"H\u{1F600}"))
+        );
+        assert_eq!(string(r#"r"\n""#), Some(Literal::from("\\n")));
+        assert_eq!(string(r##"r#"a"b"#"##), Some(Literal::from("a\"b")));
+
+        let error = |source: &str| tokenize(source.to_string()).unwrap_err();
+
+        let span = Span {
+            start: 3,
+            end: 5,
+            line: 1,
+            column: 4,
+        };
+        assert_eq!(error(r#""ab\q""#), InvalidEscape { span });
+        assert!(matches!(error(r#""\u{D800}""#), InvalidEscape { .. }));
+        assert!(matches!(error(r#""\u{1234567}""#), InvalidEscape { .. }));
+        assert!(matches!(error(r##"r#"a""##), UnterminatedString { .. }));
+    }
+
+    #[test]
+    fn test_numbers() {
+        let number = |source: &str| tokenize(source.to_string()).unwrap()[0].literal.clone();
+
+        assert_eq!(number("0xFF"), Some(Literal::Int(255)));
+        assert_eq!(number("0b1010"), Some(Literal::Int(10)));
+        assert_eq!(number("9_223_372_036_854_775_807"), Some(Literal::Int(i64::MAX)));
+        assert_eq!(number("1.0"), Some(Literal::Number(1.)));
+        assert_eq!(number("1e-9"), Some(Literal::Number(1e-9)));
+        assert_eq!(number("6.02E23"), Some(Literal::Number(6.02e23)));
+        assert_eq!(
+            number("1_000_000.000_1"),
+            Some(Literal::Number(1_000_000.000_1))
+        );
+
+        for source in ["0x", "0b2", "1e", "1e+", "1_", "1_.5", "0xFG", "12ab"] {
+            let error = tokenize(source.to_string()).unwrap_err();
+            assert!(
+                matches!(error, MalformedNumber { span } if span.start == 0),
+                "{source}"
+            );
+        }
+
+        for source in ["9223372036854775808", "0x8000_0000_0000_0000"] {
+            let error = tokenize(source.to_string()).unwrap_err();
+            assert!(matches!(error, IntegerTooLarge { .. }), "{source}");
+        }
+    }
+
+    #[test]
+    fn test_block_comments() {
+        let tokens = tokenize("/* a /* b\n */ c */ x /**/ /*\n*/ y".to_string()).unwrap();
+
+        assert_eq!(tokens.len(), 3);
+        assert_eq!((tokens[0].lexeme.as_str(), tokens[0].span.line), ("x", 2));
+        assert_eq!((tokens[1].lexeme.as_str(), tokens[1].span.line), ("y", 3));
+
+        let error = tokenize("x\n  /* /* */".to_string()).unwrap_err();
+        let span = Span {
+            start: 4,
+            end: 6,
+            line: 2,
+            column: 3,
+        };
+        assert_eq!(error, UnterminatedComment { span });
+    }
 }
@@ -1,35 +1,67 @@
 use std::fmt;
 
+use super::span::Span;
+
 #[derive(Clone, Copy, PartialEq)]
 pub enum ScanError {
     UnexpectedChar {
         ch: char,
-        line: usize,
+        span: Span,
     },
     ExpectedDigitAfterDot {
-        line: usize,
+        span: Span,
     },
     UnterminatedString {
-        line: usize,
+        span: Span,
+    },
+    InvalidEscape {
+        span: Span,
+    },
+    UnterminatedComment {
+        span: Span,
+    },
+    MalformedNumber {
+        span: Span,
+    },
+    IntegerTooLarge {
+        span: Span,
     },
 }
 
-impl fmt::Display for ScanError {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+impl ScanError {
+    /// Returns the span of source code the error is about.
+    pub fn span(&self) -> Span {
+        match self {
+            ScanError::UnexpectedChar { span, .. }
+            | ScanError::ExpectedDigitAfterDot { span }
+            | ScanError::UnterminatedString { span }
+            | ScanError::InvalidEscape { span }
+            | ScanError::UnterminatedComment { span }
+            | ScanError::MalformedNumber { span }
+            | ScanError::IntegerTooLarge { span } => *span,
+        }
+    }
+
+    /// Returns a description of the error, without its location.
+    pub fn message(&self) -> String {
         match self {
-            ScanError::UnexpectedChar { ch, line } => {
-				write!(f, "[line {line}] Unexpected character `{}`", ch)
-			}
-            ScanError::ExpectedDigitAfterDot { line } => {
-                write!(f, "[line {line}] Digit expected after dot")
-            }
-            ScanError::UnterminatedString { line } => {
-                write!(f, "[line {line}] Unterminated string")
-            }
+            ScanError::UnexpectedChar { ch, .. } => format!("Unexpected character `{}`", ch),
+            ScanError::ExpectedDigitAfterDot { .. } => "Digit expected after dot".into(),
+            ScanError::UnterminatedString { .. } => "Unterminated string".into(),
+            ScanError::InvalidEscape { .. } => "Invalid escape sequence".into(),
+            ScanError::UnterminatedComment { .. } => "Unterminated block comment".into(),
+            ScanError::MalformedNumber { .. } => "Malformed number literal".into(),
+            ScanError::IntegerTooLarge { .. } => "Integer literal does not fit in 64 bits".into(),
         }
     }
 }
 
+impl fmt::Display for ScanError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[line {}] {}", self.span().line, self.message())
+    }
+}
+
 impl fmt::Debug for ScanError {
 	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
 		fmt::Display::fmt(self, f)
@@ -0,0 +1,12 @@
+/// The location of a piece of source code, such as a token.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Span {
+    /// Byte offset of the first character.
+    pub start: usize,
+    /// Byte offset right after the last character.
+    pub end: usize,
+    /// Line of the first character, starting at 1.
+    pub line: usize,
+    /// Column of the first character in its line, counted in characters and starting at 1.
+    pub column: usize,
+}
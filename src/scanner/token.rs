@@ -1,4 +1,4 @@
-use super::{literal::Literal, token_kind::TokenKind};
+use super::{literal::Literal, span::Span, token_kind::TokenKind};
 use std::fmt;
 
 #[derive(Clone, Debug)]
@@ -6,28 +6,33 @@ pub struct Token {
     pub kind: TokenKind,
     pub lexeme: String,
     pub literal: Option<Literal>,
-    pub line: usize,
+    pub span: Span,
 }
 
 impl Token {
-    pub fn new(kind: TokenKind, lexeme: String, literal: Literal, line: usize) -> Token {
+    pub fn new(kind: TokenKind, lexeme: String, literal: Literal, span: Span) -> Token {
         Token {
             kind,
             lexeme,
             literal: Some(literal),
-            line,
+            span,
         }
     }
 
     /// A symbol (or symbolic token) is a token that does not have a literal value.
-    pub fn symbol(kind: TokenKind, lexeme: String, line: usize) -> Token {
+    pub fn symbol(kind: TokenKind, lexeme: String, span: Span) -> Token {
         Token {
             kind,
             lexeme,
             literal: None,
-            line,
+            span,
         }
     }
+
+    /// Returns the line the token starts at.
+    pub fn line(&self) -> usize {
+        self.span.line
+    }
 }
 
 impl fmt::Display for Token {
@@ -4,23 +4,46 @@ pub enum TokenKind {
     RightParenthesis,
     LeftBrace,
     RightBrace,
+    LeftBracket,
+    RightBracket,
     Comma,
     Dot,
     Minus,
+    MinusEqual,
+    MinusMinus,
     Plus,
+    PlusEqual,
+    PlusPlus,
     Semicolon,
     Slash,
+    SlashEqual,
     Star,
+    StarEqual,
+    StarStar,
+    Percent,
+    Ampersand,
+    Pipe,
+    Caret,
+    Tilde,
+    LessLess,
+    GreaterGreater,
     Bang,
     BangEqual,
     Equal,
     EqualEqual,
+    Arrow,
     Greater,
     GreaterEqual,
     Less,
     LessEqual,
     Identifier,
     String,
+    /// The part of an interpolated string up to its first `${`.
+    InterpolationStart,
+    /// The part of an interpolated string between a `}` and the next `${`.
+    InterpolationMid,
+    /// The part of an interpolated string after its last `}`.
+    InterpolationEnd,
     Number,
     And,
     Class,
@@ -38,6 +61,12 @@ pub enum TokenKind {
     This,
     Var,
     While,
+    Break,
+    Continue,
+    Try,
+    Catch,
+    Finally,
+    Throw,
     Eof,
     Question,
     Colon,
@@ -64,6 +93,12 @@ impl TokenKind {
             "true" => TokenKind::True,
             "var" => TokenKind::Var,
             "while" => TokenKind::While,
+            "break" => TokenKind::Break,
+            "continue" => TokenKind::Continue,
+            "try" => TokenKind::Try,
+            "catch" => TokenKind::Catch,
+            "finally" => TokenKind::Finally,
+            "throw" => TokenKind::Throw,
             _ => return None,
         };
 
@@ -92,6 +127,10 @@ impl TokenKind {
                 | TokenKind::While
                 | TokenKind::Print
                 | TokenKind::Return
+                | TokenKind::Break
+                | TokenKind::Continue
+                | TokenKind::Try
+                | TokenKind::Throw
         )
     }
 }
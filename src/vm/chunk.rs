@@ -0,0 +1,151 @@
+use std::rc::Rc;
+
+use crate::{interpreter::value::Value, scanner::span::Span};
+
+macro_rules! opcodes {
+    ($($(#[$doc:meta])* $name:ident,)+) => {
+        /// A single VM instruction. Operands, if any, are encoded in the bytes that follow it.
+        #[repr(u8)]
+        #[derive(Clone, Copy, Debug, PartialEq)]
+        pub enum OpCode {
+            $($(#[$doc])* $name,)+
+        }
+
+        impl OpCode {
+            /// Decodes a byte that was written as an opcode.
+            pub fn from_byte(byte: u8) -> OpCode {
+                const ALL: &[OpCode] = &[$(OpCode::$name,)+];
+                ALL[byte as usize]
+            }
+        }
+    };
+}
+
+opcodes! {
+    /// u16 constant index. Pushes the constant.
+    Constant,
+    Nil,
+    True,
+    False,
+    Pop,
+    /// u8 depth. Pushes a copy of the value that many slots below the top, 0 being the top.
+    Dup,
+    /// u8 depth. Pops the top and inserts it back below that many values.
+    Bury,
+    /// u8 slot, relative to the current frame.
+    GetLocal,
+    /// u8 slot. Leaves the assigned value on the stack.
+    SetLocal,
+    /// u16 global number, from the `GlobalNames` the program was compiled with.
+    GetGlobal,
+    /// u16 global number. Pops the value.
+    DefineGlobal,
+    /// u16 global number. Leaves the assigned value on the stack.
+    SetGlobal,
+    /// u8 index into the current closure's upvalues.
+    GetUpvalue,
+    /// u8 index. Leaves the assigned value on the stack.
+    SetUpvalue,
+    /// u16 constant index of the name. Replaces the instance with the property.
+    GetProperty,
+    /// u16 constant index of the name. Pops the value and the instance, pushes the value.
+    SetProperty,
+    /// u16 constant index of the name. Pops the superclass and `this`, pushes the bound method.
+    GetSuper,
+    Equal,
+    NotEqual,
+    Greater,
+    GreaterEqual,
+    Less,
+    LessEqual,
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Modulo,
+    Power,
+    BitAnd,
+    BitOr,
+    BitXor,
+    ShiftLeft,
+    ShiftRight,
+    Not,
+    Negate,
+    BitNot,
+    Print,
+    /// u16 forward offset.
+    Jump,
+    /// u16 forward offset. Leaves the condition on the stack.
+    JumpIfFalse,
+    /// u16 backward offset.
+    Loop,
+    /// u8 argument count. The callee sits right below the arguments.
+    Call,
+    /// u16 index into the chunk's prototypes, followed by an (u8 is_local, u8 index) pair
+    /// per captured variable.
+    Closure,
+    /// Moves the local on top of the stack to the heap, then pops it.
+    CloseUpvalue,
+    Return,
+    /// u16 constant index of the superclass' name, which is only used for errors.
+    /// Errors if the value on top of the stack is not a class, leaving it there otherwise.
+    CheckSuperclass,
+    /// u16 constant index of the name, u8 method count, u8 whether there is a superclass.
+    /// Pops the methods and pushes the class. The superclass, if any, is right below the
+    /// methods and stays on the stack.
+    Class,
+    /// u16 item count. Pops the items and pushes a list of them.
+    BuildList,
+    /// u16 part count. Pops the parts of an interpolated string and pushes them joined.
+    Concat,
+    /// u16 entry count. Pops a key and a value per entry and pushes a map of them.
+    BuildMap,
+    /// Pops the index and the list or map, pushes the element.
+    GetIndex,
+    /// Pops the value, the index and the list or map, pushes the value.
+    SetIndex,
+    /// u16 forward offset. Until the matching `PopHandler`, errors unwind the stack back to
+    /// how it is now, push the error and jump there.
+    PushHandler,
+    PopHandler,
+    /// Pops the value and raises it, or raises the error it holds again if it was caught.
+    Throw,
+}
+
+/// A compiled function, before any variables are captured.
+#[derive(Debug)]
+pub struct Proto {
+    pub name: String,
+    pub arity: usize,
+    pub is_initializer: bool,
+    pub upvalue_count: usize,
+    pub chunk: Chunk,
+}
+
+/// A sequence of bytecode along with the data it refers to.
+#[derive(Debug, Default)]
+pub struct Chunk {
+    pub code: Vec<u8>,
+    /// Where each byte of `code` came from, for errors.
+    pub spans: Vec<Span>,
+    pub constants: Vec<Value>,
+    /// The functions declared directly inside this one.
+    pub protos: Vec<Rc<Proto>>,
+}
+
+impl Chunk {
+    pub fn write(&mut self, byte: u8, span: Span) {
+        self.code.push(byte);
+        self.spans.push(span);
+    }
+
+    pub fn write_u16(&mut self, value: u16, span: Span) {
+        for byte in value.to_be_bytes() {
+            self.write(byte, span);
+        }
+    }
+
+    pub fn read_u16(&self, offset: usize) -> u16 {
+        u16::from_be_bytes([self.code[offset], self.code[offset + 1]])
+    }
+}
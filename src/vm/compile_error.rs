@@ -0,0 +1,57 @@
+use std::fmt;
+
+use crate::scanner::span::Span;
+
+/// A limit of the bytecode format that a program exceeds.
+#[derive(Clone, Copy)]
+pub enum CompileError {
+    TooManyLocals { span: Span },
+    TooManyUpvalues { span: Span },
+    TooManyConstants { span: Span },
+    JumpTooLarge { span: Span },
+    TooManyItems { span: Span },
+    TooManyGlobals { span: Span },
+    TooManyMethods { span: Span },
+}
+
+impl CompileError {
+    /// Returns the span of source code the error is about.
+    pub fn span(&self) -> Span {
+        match self {
+            CompileError::TooManyLocals { span }
+            | CompileError::TooManyUpvalues { span }
+            | CompileError::TooManyConstants { span }
+            | CompileError::JumpTooLarge { span }
+            | CompileError::TooManyItems { span }
+            | CompileError::TooManyGlobals { span }
+            | CompileError::TooManyMethods { span } => *span,
+        }
+    }
+
+    /// Returns a description of the error, without its location.
+    pub fn message(&self) -> String {
+        match self {
+            CompileError::TooManyLocals { .. } => "Too many local variables in function".into(),
+            CompileError::TooManyUpvalues { .. } => {
+                "Too many captured variables in function".into()
+            }
+            CompileError::TooManyConstants { .. } => "Too many constants in one function".into(),
+            CompileError::JumpTooLarge { .. } => "Too much code to jump over".into(),
+            CompileError::TooManyItems { .. } => "Too many items in one literal".into(),
+            CompileError::TooManyGlobals { .. } => "Too many global variables".into(),
+            CompileError::TooManyMethods { .. } => "Too many methods in one class".into(),
+        }
+    }
+}
+
+impl fmt::Display for CompileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[line {}] {}", self.span().line, self.message())
+    }
+}
+
+impl fmt::Debug for CompileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
@@ -0,0 +1,1060 @@
+use std::rc::Rc;
+
+use super::{
+    chunk::{Chunk, OpCode, Proto},
+    compile_error::CompileError::{self, *},
+    GlobalNames,
+};
+use crate::{
+    interpreter::{class::INITIALIZER, value::Value},
+    parser::{
+        expr::Expr,
+        stmt::{Block, FunctionDecl, Stmt},
+    },
+    scanner::{literal::Literal, span::Span, token_kind::TokenKind as TK},
+};
+
+#[derive(Clone, Copy, PartialEq)]
+enum FunctionKind {
+    Script,
+    Function,
+    Method,
+    Initializer,
+}
+
+/// Compiles a resolved program into a function that runs it when called without arguments.
+/// Variables are bound here again rather than through the resolver's depths, because the VM
+/// needs stack slots and captures instead. Globals are numbered with `names`, which should
+/// belong to the VM that runs the program.
+pub fn compile(stmts: &[Stmt], names: &mut GlobalNames) -> Result<Rc<Proto>, CompileError> {
+    let mut compiler = Compiler {
+        functions: vec![FunctionState::new("script".into(), 0, FunctionKind::Script)],
+        names,
+        span: Span::default(),
+    };
+
+    for stmt in stmts {
+        compiler.stmt(stmt)?;
+    }
+
+    compiler.emit_return();
+
+    let script = compiler
+        .functions
+        .pop()
+        .expect("The script should be compiling");
+    Ok(Rc::new(script.proto))
+}
+
+/// A variable captured by a closure: either a local of the enclosing function, or one
+/// of the enclosing function's own captures.
+#[derive(Clone, Copy, PartialEq)]
+struct Capture {
+    is_local: bool,
+    index: u8,
+}
+
+struct Local {
+    name: String,
+    depth: usize,
+    // Captured locals must be moved off the stack when they go out of scope.
+    is_captured: bool,
+}
+
+struct Loop {
+    label: Option<String>,
+    // Locals deeper than this belong to the loop and are dropped when jumping out of it.
+    scope_depth: usize,
+    // Jumps still waiting for the position to land on.
+    breaks: Vec<usize>,
+    continues: Vec<usize>,
+}
+
+// A `try` statement whose handler protects the code being compiled.
+#[derive(Clone)]
+struct Try {
+    // How many loops enclose the statement. Jumps to them leave it.
+    loop_depth: usize,
+    // Ran by jumps and returns leaving the statement, before they land.
+    finally: Option<Block>,
+}
+
+struct FunctionState {
+    proto: Proto,
+    kind: FunctionKind,
+    // Stack slots of the function's frame, in order.
+    locals: Vec<Local>,
+    captures: Vec<Capture>,
+    scope_depth: usize,
+    loops: Vec<Loop>,
+    tries: Vec<Try>,
+}
+
+impl FunctionState {
+    fn new(name: String, arity: usize, kind: FunctionKind) -> Self {
+        // Slot zero holds the receiver in methods and the called function otherwise,
+        // which can't be named.
+        let receiver = match kind {
+            FunctionKind::Method | FunctionKind::Initializer => "this",
+            FunctionKind::Script | FunctionKind::Function => "",
+        };
+
+        Self {
+            proto: Proto {
+                name,
+                arity,
+                is_initializer: kind == FunctionKind::Initializer,
+                upvalue_count: 0,
+                chunk: Chunk::default(),
+            },
+            kind,
+            locals: vec![Local {
+                name: receiver.into(),
+                depth: 0,
+                is_captured: false,
+            }],
+            captures: Vec::new(),
+            scope_depth: 0,
+            loops: Vec::new(),
+            tries: Vec::new(),
+        }
+    }
+}
+
+struct Compiler<'a> {
+    // The function being compiled is last, enclosed by the ones before it.
+    functions: Vec<FunctionState>,
+    names: &'a mut GlobalNames,
+    // Where the code being emitted comes from.
+    span: Span,
+}
+
+impl Compiler<'_> {
+    fn stmt(&mut self, stmt: &Stmt) -> Result<(), CompileError> {
+        match stmt {
+            Stmt::Expr(expr) => {
+                self.expr(expr)?;
+                self.emit(OpCode::Pop);
+            }
+
+            Stmt::Print(expr) => {
+                self.expr(expr)?;
+                self.emit(OpCode::Print);
+            }
+
+            Stmt::Var { name, init } => {
+                self.span = name.span;
+                // Declared first so that closures in the initializer can capture it.
+                self.declare_variable(&name.lexeme)?;
+                self.expr(init)?;
+                self.define_variable(&name.lexeme)?;
+            }
+
+            Stmt::Block(block) => self.block(&block.stmts)?,
+
+            Stmt::If {
+                condition,
+                then_branch,
+                else_branch,
+            } => {
+                self.expr(condition)?;
+                let then_jump = self.emit_jump(OpCode::JumpIfFalse);
+                self.emit(OpCode::Pop);
+                self.stmt(then_branch)?;
+
+                let else_jump = self.emit_jump(OpCode::Jump);
+                self.patch_jump(then_jump)?;
+                self.emit(OpCode::Pop);
+                if let Some(else_branch) = else_branch {
+                    self.stmt(else_branch)?;
+                }
+                self.patch_jump(else_jump)?;
+            }
+
+            Stmt::While {
+                condition,
+                body,
+                increment,
+                label,
+            } => {
+                let start = self.chunk().code.len();
+                self.expr(condition)?;
+                let exit_jump = self.emit_jump(OpCode::JumpIfFalse);
+                self.emit(OpCode::Pop);
+
+                let scope_depth = self.current().scope_depth;
+                self.current().loops.push(Loop {
+                    label: label.as_ref().map(|label| label.lexeme.clone()),
+                    scope_depth,
+                    breaks: Vec::new(),
+                    continues: Vec::new(),
+                });
+                self.stmt(body)?;
+                let finished = self
+                    .current()
+                    .loops
+                    .pop()
+                    .expect("The loop should be compiling");
+
+                for jump in finished.continues {
+                    self.patch_jump(jump)?;
+                }
+                if let Some(increment) = increment {
+                    self.expr(increment)?;
+                    self.emit(OpCode::Pop);
+                }
+                self.emit_loop(start)?;
+
+                self.patch_jump(exit_jump)?;
+                self.emit(OpCode::Pop);
+
+                // Breaks leave from the body, where the condition is already popped.
+                for jump in finished.breaks {
+                    self.patch_jump(jump)?;
+                }
+            }
+
+            Stmt::Break { keyword, label } | Stmt::Continue { keyword, label } => {
+                self.span = keyword.span;
+
+                let loops = &self.current().loops;
+                let target = match label {
+                    Some(label) => loops
+                        .iter()
+                        .rposition(|l| l.label.as_ref() == Some(&label.lexeme)),
+                    None => loops.len().checked_sub(1),
+                }
+                .expect("Jumps should always be inside the loop they target");
+
+                let scope_depth = loops[target].scope_depth;
+                self.exit_tries(target + 1)?;
+                self.span = keyword.span;
+                self.discard_locals(scope_depth);
+                let jump = self.emit_jump(OpCode::Jump);
+
+                let target = &mut self.current().loops[target];
+                match stmt {
+                    Stmt::Break { .. } => target.breaks.push(jump),
+                    _ => target.continues.push(jump),
+                }
+            }
+
+            Stmt::Function(decl) => {
+                self.span = decl.name.span;
+                // Declared before compiling the body so that it can call itself.
+                self.declare_variable(&decl.name.lexeme)?;
+                self.function(decl, FunctionKind::Function)?;
+                self.define_variable(&decl.name.lexeme)?;
+            }
+
+            Stmt::Return { keyword, value } => {
+                self.span = keyword.span;
+
+                // Initializers always hand back the instance, like when they end normally.
+                match value {
+                    Some(value) => self.expr(value)?,
+                    None if self.current().kind == FunctionKind::Initializer => {
+                        self.emit_with(OpCode::GetLocal, 0)
+                    }
+                    None => self.emit(OpCode::Nil),
+                }
+
+                // The value waits on the stack while finally blocks run.
+                if !self.current().tries.is_empty() {
+                    self.add_local("")?;
+                    self.exit_tries(0)?;
+                    self.current().locals.pop();
+                }
+
+                self.span = keyword.span;
+                self.emit(OpCode::Return);
+            }
+
+            Stmt::Class {
+                name,
+                superclass,
+                methods,
+            } => {
+                self.span = name.span;
+                let name_constant = self.identifier_constant(&name.lexeme)?;
+
+                // A local class takes its slot before the superclass is bound, and is only
+                // filled in once built.
+                let slot = if self.current().scope_depth > 0 {
+                    self.emit(OpCode::Nil);
+                    self.add_local(&name.lexeme)?;
+                    Some(self.current().locals.len() - 1)
+                } else {
+                    None
+                };
+
+                if let Some(superclass) = superclass {
+                    let Expr::Variable {
+                        name: super_name, ..
+                    } = superclass
+                    else {
+                        panic!("Superclass should always be a variable expression");
+                    };
+
+                    self.expr(superclass)?;
+                    self.span = super_name.span;
+                    let super_constant = self.identifier_constant(&super_name.lexeme)?;
+                    self.emit_u16(OpCode::CheckSuperclass, super_constant);
+
+                    // Methods capture the superclass like any other local, as `super`.
+                    self.begin_scope();
+                    self.add_local("super")?;
+                }
+
+                for method in methods {
+                    let kind = if method.name.lexeme == INITIALIZER {
+                        FunctionKind::Initializer
+                    } else {
+                        FunctionKind::Method
+                    };
+
+                    self.function(method, kind)?;
+                }
+
+                self.span = name.span;
+                let method_count =
+                    u8::try_from(methods.len()).map_err(|_| TooManyMethods { span: name.span })?;
+                self.emit_u16(OpCode::Class, name_constant);
+                self.emit_byte(method_count);
+                self.emit_byte(superclass.is_some() as u8);
+
+                match slot {
+                    Some(slot) => {
+                        self.emit_with(OpCode::SetLocal, slot as u8);
+                        self.emit(OpCode::Pop);
+                    }
+                    None => {
+                        let global = self.global_index(&name.lexeme)?;
+                        self.emit_u16(OpCode::DefineGlobal, global);
+                    }
+                }
+
+                if superclass.is_some() {
+                    self.end_scope();
+                }
+            }
+
+            // The finally block is compiled once per way of leaving the others: after the try
+            // block, after the catch block, on errors, which are raised again afterwards, and
+            // before every jump or return out of them.
+            Stmt::Try {
+                keyword,
+                body,
+                catch,
+                finally,
+            } => {
+                self.span = keyword.span;
+                let loop_depth = self.current().loops.len();
+                let protect = Try {
+                    loop_depth,
+                    finally: finally.clone(),
+                };
+
+                let handler = self.emit_jump(OpCode::PushHandler);
+                self.current().tries.push(protect.clone());
+                self.block(&body.stmts)?;
+                self.current().tries.pop();
+                self.span = keyword.span;
+                self.emit(OpCode::PopHandler);
+                self.finally(finally)?;
+                let mut end_jumps = vec![self.emit_jump(OpCode::Jump)];
+
+                // The error is on top of the stack when landing here.
+                self.patch_jump(handler)?;
+                match (catch, finally) {
+                    (Some((name, catch)), None) => {
+                        self.begin_scope();
+                        self.span = name.span;
+                        self.add_local(&name.lexeme)?;
+                        for stmt in &catch.stmts {
+                            self.stmt(stmt)?;
+                        }
+                        self.end_scope();
+                    }
+
+                    // Errors in the catch block must run the finally block too.
+                    (Some((name, catch)), Some(finally)) => {
+                        self.begin_scope();
+                        self.span = name.span;
+                        self.add_local(&name.lexeme)?;
+                        let handler = self.emit_jump(OpCode::PushHandler);
+                        self.current().tries.push(protect);
+                        for stmt in &catch.stmts {
+                            self.stmt(stmt)?;
+                        }
+                        self.current().tries.pop();
+                        self.span = keyword.span;
+                        self.emit(OpCode::PopHandler);
+                        self.end_scope();
+                        self.block(&finally.stmts)?;
+                        end_jumps.push(self.emit_jump(OpCode::Jump));
+
+                        // Both the caught error and the new one are on the stack.
+                        self.patch_jump(handler)?;
+                        self.rethrow_after(&finally.stmts, 2)?;
+                    }
+
+                    (None, Some(finally)) => self.rethrow_after(&finally.stmts, 1)?,
+
+                    (None, None) => panic!("A try statement should have a catch or finally block"),
+                }
+
+                for jump in end_jumps {
+                    self.patch_jump(jump)?;
+                }
+            }
+
+            Stmt::Throw { keyword, value } => {
+                self.expr(value)?;
+                self.span = keyword.span;
+                self.emit(OpCode::Throw);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn block(&mut self, stmts: &[Stmt]) -> Result<(), CompileError> {
+        self.begin_scope();
+        for stmt in stmts {
+            self.stmt(stmt)?;
+        }
+        self.end_scope();
+
+        Ok(())
+    }
+
+    fn finally(&mut self, finally: &Option<Block>) -> Result<(), CompileError> {
+        match finally {
+            Some(finally) => self.block(&finally.stmts),
+            None => Ok(()),
+        }
+    }
+
+    // Emits the code leaving the try statements inside the first `loop_depth` loops, innermost
+    // first: each one's handler is removed, then its finally block runs.
+    // Each finally block is compiled as if outside its statement, so that jumping out of it
+    // only leaves the statements around that one.
+    fn exit_tries(&mut self, loop_depth: usize) -> Result<(), CompileError> {
+        let tries = self.current().tries.clone();
+
+        while let Some(exited) = self
+            .current()
+            .tries
+            .pop_if(|exited| exited.loop_depth >= loop_depth)
+        {
+            self.emit(OpCode::PopHandler);
+            self.finally(&exited.finally)?;
+        }
+
+        self.current().tries = tries;
+        Ok(())
+    }
+
+    // Runs the finally block of a try statement while `count` values, the error on top, sit
+    // on the stack without being locals, then raises the error again.
+    fn rethrow_after(&mut self, finally: &[Stmt], count: usize) -> Result<(), CompileError> {
+        self.begin_scope();
+        for _ in 0..count {
+            self.add_local("")?;
+            // Jumping out of the finally block must close them, in case one is a caught error
+            // that a closure captured.
+            self.current()
+                .locals
+                .last_mut()
+                .expect("The local was just added")
+                .is_captured = true;
+        }
+
+        self.block(finally)?;
+        self.emit(OpCode::Throw);
+
+        // Throwing never falls through, so they are forgotten without being popped.
+        let state = self.current();
+        state.scope_depth -= 1;
+        state.locals.truncate(state.locals.len() - count);
+
+        Ok(())
+    }
+
+    fn expr(&mut self, expr: &Expr) -> Result<(), CompileError> {
+        match expr {
+            Expr::Literal(literal) => match literal {
+                Literal::Bool(true) => self.emit(OpCode::True),
+                Literal::Bool(false) => self.emit(OpCode::False),
+                Literal::Nil => self.emit(OpCode::Nil),
+                literal => {
+                    let constant = self.make_constant(literal.into())?;
+                    self.emit_u16(OpCode::Constant, constant);
+                }
+            },
+
+            Expr::Unary(op, r) => {
+                self.expr(r)?;
+                self.span = op.span;
+                match op.kind {
+                    TK::Minus => self.emit(OpCode::Negate),
+                    TK::Bang => self.emit(OpCode::Not),
+                    TK::Tilde => self.emit(OpCode::BitNot),
+                    _ => panic!("Invalid unary operator `{:?}`", op.kind),
+                }
+            }
+
+            Expr::Binary(l, op, r) => {
+                self.expr(l)?;
+                self.expr(r)?;
+                self.span = op.span;
+                self.emit(binary_op(op.kind));
+            }
+
+            Expr::Group(expr) => self.expr(expr)?,
+
+            Expr::Interpolation(parts) => {
+                for part in parts {
+                    self.expr(part)?;
+                }
+
+                let count =
+                    u16::try_from(parts.len()).map_err(|_| TooManyItems { span: self.span })?;
+                self.emit_u16(OpCode::Concat, count);
+            }
+
+            Expr::Ternary(expr, if_, else_) => {
+                self.expr(expr)?;
+                let else_jump = self.emit_jump(OpCode::JumpIfFalse);
+                self.emit(OpCode::Pop);
+                self.expr(if_)?;
+
+                let end_jump = self.emit_jump(OpCode::Jump);
+                self.patch_jump(else_jump)?;
+                self.emit(OpCode::Pop);
+                self.expr(else_)?;
+                self.patch_jump(end_jump)?;
+            }
+
+            Expr::Variable { name, .. } => {
+                self.span = name.span;
+                self.get_variable(&name.lexeme)?;
+            }
+
+            Expr::Assign { name, value, .. } => {
+                self.expr(value)?;
+                self.span = name.span;
+                self.set_variable(&name.lexeme)?;
+            }
+
+            // The parts of the target are evaluated once and duplicated, so that they can be used
+            // both to read and to write. For postfix updates, a copy of the old value is buried
+            // below them and is what remains once the new value is popped.
+            Expr::Update {
+                target,
+                op,
+                value,
+                postfix,
+            } => {
+                let keep_old = |compiler: &mut Self, depth: u8| {
+                    if *postfix {
+                        compiler.emit_with(OpCode::Dup, 0);
+                        compiler.emit_with(OpCode::Bury, depth);
+                    }
+                };
+
+                match target.as_ref() {
+                    Expr::Variable { name, .. } => {
+                        self.span = name.span;
+                        self.get_variable(&name.lexeme)?;
+                        keep_old(self, 1);
+                        self.expr(value)?;
+                        self.span = op.span;
+                        self.emit(binary_op(op.kind));
+                        self.span = name.span;
+                        self.set_variable(&name.lexeme)?;
+                    }
+
+                    Expr::Get { object, name } => {
+                        self.expr(object)?;
+                        self.span = name.span;
+                        let constant = self.identifier_constant(&name.lexeme)?;
+                        self.emit_with(OpCode::Dup, 0);
+                        self.emit_u16(OpCode::GetProperty, constant);
+                        keep_old(self, 2);
+                        self.expr(value)?;
+                        self.span = op.span;
+                        self.emit(binary_op(op.kind));
+                        self.span = name.span;
+                        self.emit_u16(OpCode::SetProperty, constant);
+                    }
+
+                    Expr::Index {
+                        object,
+                        bracket,
+                        index,
+                    } => {
+                        self.expr(object)?;
+                        self.expr(index)?;
+                        self.span = bracket.span;
+                        self.emit_with(OpCode::Dup, 1);
+                        self.emit_with(OpCode::Dup, 1);
+                        self.emit(OpCode::GetIndex);
+                        keep_old(self, 3);
+                        self.expr(value)?;
+                        self.span = op.span;
+                        self.emit(binary_op(op.kind));
+                        self.span = bracket.span;
+                        self.emit(OpCode::SetIndex);
+                    }
+
+                    _ => panic!("Invalid update target `{}`", target),
+                }
+
+                if *postfix {
+                    self.emit(OpCode::Pop);
+                }
+            }
+
+            // The left side is left on the stack as the result when it short-circuits.
+            Expr::Logical(l, op, r) => {
+                self.expr(l)?;
+
+                let end_jump = match op.kind {
+                    TK::And => self.emit_jump(OpCode::JumpIfFalse),
+                    TK::Or => {
+                        let else_jump = self.emit_jump(OpCode::JumpIfFalse);
+                        let end_jump = self.emit_jump(OpCode::Jump);
+                        self.patch_jump(else_jump)?;
+                        end_jump
+                    }
+                    _ => panic!("Invalid logical operator `{:?}`", op.kind),
+                };
+
+                self.emit(OpCode::Pop);
+                self.expr(r)?;
+                self.patch_jump(end_jump)?;
+            }
+
+            Expr::Call {
+                callee,
+                paren,
+                args,
+            } => {
+                self.expr(callee)?;
+                for arg in args {
+                    self.expr(arg)?;
+                }
+
+                // The parser already limits arguments to what fits in a byte.
+                self.span = paren.span;
+                self.emit_with(OpCode::Call, args.len() as u8);
+            }
+
+            Expr::Get { object, name } => {
+                self.expr(object)?;
+                self.span = name.span;
+                let constant = self.identifier_constant(&name.lexeme)?;
+                self.emit_u16(OpCode::GetProperty, constant);
+            }
+
+            Expr::Set {
+                object,
+                name,
+                value,
+            } => {
+                self.expr(object)?;
+                self.expr(value)?;
+                self.span = name.span;
+                let constant = self.identifier_constant(&name.lexeme)?;
+                self.emit_u16(OpCode::SetProperty, constant);
+            }
+
+            Expr::Lambda(decl) => {
+                self.span = decl.name.span;
+                self.function(decl, FunctionKind::Function)?;
+            }
+
+            Expr::List { bracket, items } => {
+                for item in items {
+                    self.expr(item)?;
+                }
+
+                self.span = bracket.span;
+                let count =
+                    u16::try_from(items.len()).map_err(|_| TooManyItems { span: bracket.span })?;
+                self.emit_u16(OpCode::BuildList, count);
+            }
+
+            Expr::Map { brace, entries } => {
+                for (key, value) in entries {
+                    self.expr(key)?;
+                    self.expr(value)?;
+                }
+
+                self.span = brace.span;
+                let count =
+                    u16::try_from(entries.len()).map_err(|_| TooManyItems { span: brace.span })?;
+                self.emit_u16(OpCode::BuildMap, count);
+            }
+
+            Expr::Index {
+                object,
+                bracket,
+                index,
+            } => {
+                self.expr(object)?;
+                self.expr(index)?;
+                self.span = bracket.span;
+                self.emit(OpCode::GetIndex);
+            }
+
+            Expr::SetIndex {
+                object,
+                bracket,
+                index,
+                value,
+            } => {
+                self.expr(object)?;
+                self.expr(index)?;
+                self.expr(value)?;
+                self.span = bracket.span;
+                self.emit(OpCode::SetIndex);
+            }
+
+            Expr::This { keyword, .. } => {
+                self.span = keyword.span;
+                self.get_variable("this")?;
+            }
+
+            Expr::Super {
+                keyword, method, ..
+            } => {
+                self.span = keyword.span;
+                self.get_variable("this")?;
+                self.get_variable("super")?;
+
+                self.span = method.span;
+                let constant = self.identifier_constant(&method.lexeme)?;
+                self.emit_u16(OpCode::GetSuper, constant);
+            }
+        }
+
+        Ok(())
+    }
+
+    // Compiles the function in its own state, then emits the code creating a closure of it.
+    fn function(&mut self, decl: &FunctionDecl, kind: FunctionKind) -> Result<(), CompileError> {
+        self.functions.push(FunctionState::new(
+            decl.name.lexeme.clone(),
+            decl.params.len(),
+            kind,
+        ));
+
+        // Never closed, since returning discards the whole frame anyway.
+        self.begin_scope();
+
+        for param in &decl.params {
+            self.span = param.span;
+            self.add_local(&param.lexeme)?;
+        }
+
+        for stmt in &decl.body {
+            self.stmt(stmt)?;
+        }
+
+        self.emit_return();
+
+        let mut compiled = self
+            .functions
+            .pop()
+            .expect("The function should be compiling");
+        compiled.proto.upvalue_count = compiled.captures.len();
+
+        self.span = decl.name.span;
+        let protos = &mut self.chunk().protos;
+        let index = u16::try_from(protos.len()).map_err(|_| TooManyConstants {
+            span: decl.name.span,
+        })?;
+        protos.push(Rc::new(compiled.proto));
+
+        self.emit_u16(OpCode::Closure, index);
+        for capture in compiled.captures {
+            self.emit_byte(capture.is_local as u8);
+            self.emit_byte(capture.index);
+        }
+
+        Ok(())
+    }
+
+    fn get_variable(&mut self, name: &str) -> Result<(), CompileError> {
+        let function = self.functions.len() - 1;
+
+        if let Some(slot) = self.resolve_local(function, name) {
+            self.emit_with(OpCode::GetLocal, slot);
+        } else if let Some(index) = self.resolve_capture(function, name)? {
+            self.emit_with(OpCode::GetUpvalue, index);
+        } else {
+            let global = self.global_index(name)?;
+            self.emit_u16(OpCode::GetGlobal, global);
+        }
+
+        Ok(())
+    }
+
+    fn set_variable(&mut self, name: &str) -> Result<(), CompileError> {
+        let function = self.functions.len() - 1;
+
+        if let Some(slot) = self.resolve_local(function, name) {
+            self.emit_with(OpCode::SetLocal, slot);
+        } else if let Some(index) = self.resolve_capture(function, name)? {
+            self.emit_with(OpCode::SetUpvalue, index);
+        } else {
+            let global = self.global_index(name)?;
+            self.emit_u16(OpCode::SetGlobal, global);
+        }
+
+        Ok(())
+    }
+
+    // Returns the slot of the innermost local called `name` in the given function.
+    fn resolve_local(&self, function: usize, name: &str) -> Option<u8> {
+        self.functions[function]
+            .locals
+            .iter()
+            .rposition(|local| local.name == name)
+            .map(|slot| slot as u8)
+    }
+
+    // Returns the index of the capture of `name` in the given function, capturing it
+    // through every enclosing function if needed.
+    // Returns `None` for globals.
+    fn resolve_capture(&mut self, function: usize, name: &str) -> Result<Option<u8>, CompileError> {
+        if function == 0 {
+            return Ok(None);
+        }
+
+        if let Some(slot) = self.resolve_local(function - 1, name) {
+            self.functions[function - 1].locals[slot as usize].is_captured = true;
+            let capture = Capture {
+                is_local: true,
+                index: slot,
+            };
+            return self.add_capture(function, capture).map(Some);
+        }
+
+        if let Some(index) = self.resolve_capture(function - 1, name)? {
+            let capture = Capture {
+                is_local: false,
+                index,
+            };
+            return self.add_capture(function, capture).map(Some);
+        }
+
+        Ok(None)
+    }
+
+    fn add_capture(&mut self, function: usize, capture: Capture) -> Result<u8, CompileError> {
+        let captures = &mut self.functions[function].captures;
+
+        if let Some(index) = captures.iter().position(|c| *c == capture) {
+            return Ok(index as u8);
+        }
+
+        if captures.len() > u8::MAX as usize {
+            return Err(TooManyUpvalues { span: self.span });
+        }
+
+        captures.push(capture);
+        Ok((captures.len() - 1) as u8)
+    }
+
+    fn add_local(&mut self, name: &str) -> Result<(), CompileError> {
+        let span = self.span;
+        let state = self.current();
+
+        if state.locals.len() > u8::MAX as usize {
+            return Err(TooManyLocals { span });
+        }
+
+        state.locals.push(Local {
+            name: name.into(),
+            depth: state.scope_depth,
+            is_captured: false,
+        });
+
+        Ok(())
+    }
+
+    // Locals live in the stack slot their initial value is pushed to, so only globals
+    // need to be told apart.
+    fn declare_variable(&mut self, name: &str) -> Result<(), CompileError> {
+        if self.current().scope_depth > 0 {
+            self.add_local(name)?;
+        }
+
+        Ok(())
+    }
+
+    fn define_variable(&mut self, name: &str) -> Result<(), CompileError> {
+        if self.current().scope_depth == 0 {
+            let global = self.global_index(name)?;
+            self.emit_u16(OpCode::DefineGlobal, global);
+        }
+
+        Ok(())
+    }
+
+    fn begin_scope(&mut self) {
+        self.current().scope_depth += 1;
+    }
+
+    fn end_scope(&mut self) {
+        let state = self.current();
+        state.scope_depth -= 1;
+        let depth = state.scope_depth;
+
+        self.discard_locals(depth);
+
+        let locals = &mut self.current().locals;
+        while locals.last().is_some_and(|local| local.depth > depth) {
+            locals.pop();
+        }
+    }
+
+    // Emits the code dropping every local deeper than `depth`, without forgetting about them.
+    fn discard_locals(&mut self, depth: usize) {
+        let ops: Vec<OpCode> = self
+            .current()
+            .locals
+            .iter()
+            .rev()
+            .take_while(|local| local.depth > depth)
+            .map(|local| match local.is_captured {
+                true => OpCode::CloseUpvalue,
+                false => OpCode::Pop,
+            })
+            .collect();
+
+        for op in ops {
+            self.emit(op);
+        }
+    }
+
+    fn emit_return(&mut self) {
+        match self.current().kind {
+            FunctionKind::Initializer => self.emit_with(OpCode::GetLocal, 0),
+            _ => self.emit(OpCode::Nil),
+        }
+
+        self.emit(OpCode::Return);
+    }
+
+    // Emits a jump with a placeholder offset and returns where the offset is, to patch it later.
+    fn emit_jump(&mut self, op: OpCode) -> usize {
+        self.emit_u16(op, u16::MAX);
+        self.chunk().code.len() - 2
+    }
+
+    // Makes the jump whose offset is at `offset` land on the next instruction.
+    fn patch_jump(&mut self, offset: usize) -> Result<(), CompileError> {
+        let span = self.span;
+        let chunk = self.chunk();
+        let jump =
+            u16::try_from(chunk.code.len() - offset - 2).map_err(|_| JumpTooLarge { span })?;
+
+        chunk.code[offset..offset + 2].copy_from_slice(&jump.to_be_bytes());
+        Ok(())
+    }
+
+    fn emit_loop(&mut self, start: usize) -> Result<(), CompileError> {
+        // Also skips over the loop instruction itself.
+        let jump = u16::try_from(self.chunk().code.len() - start + 3)
+            .map_err(|_| JumpTooLarge { span: self.span })?;
+
+        self.emit_u16(OpCode::Loop, jump);
+        Ok(())
+    }
+
+    fn identifier_constant(&mut self, name: &str) -> Result<u16, CompileError> {
+        let existing = self
+            .chunk()
+            .constants
+            .iter()
+            .position(|constant| matches!(constant, Value::String(s) if s == name));
+
+        match existing {
+            Some(index) => Ok(index as u16),
+            None => self.make_constant(Value::from(name)),
+        }
+    }
+
+    fn global_index(&mut self, name: &str) -> Result<u16, CompileError> {
+        self.names
+            .index(name)
+            .ok_or(TooManyGlobals { span: self.span })
+    }
+
+    fn make_constant(&mut self, value: Value) -> Result<u16, CompileError> {
+        let span = self.span;
+        let constants = &mut self.chunk().constants;
+        let index = u16::try_from(constants.len()).map_err(|_| TooManyConstants { span })?;
+
+        constants.push(value);
+        Ok(index)
+    }
+
+    fn emit(&mut self, op: OpCode) {
+        self.emit_byte(op as u8);
+    }
+
+    fn emit_with(&mut self, op: OpCode, operand: u8) {
+        self.emit(op);
+        self.emit_byte(operand);
+    }
+
+    fn emit_u16(&mut self, op: OpCode, operand: u16) {
+        self.emit(op);
+        let span = self.span;
+        self.chunk().write_u16(operand, span);
+    }
+
+    fn emit_byte(&mut self, byte: u8) {
+        let span = self.span;
+        self.chunk().write(byte, span);
+    }
+
+    fn current(&mut self) -> &mut FunctionState {
+        self.functions
+            .last_mut()
+            .expect("A function should be compiling")
+    }
+
+    fn chunk(&mut self) -> &mut Chunk {
+        &mut self.current().proto.chunk
+    }
+}
+
+fn binary_op(kind: TK) -> OpCode {
+    match kind {
+        TK::EqualEqual => OpCode::Equal,
+        TK::BangEqual => OpCode::NotEqual,
+        TK::Greater => OpCode::Greater,
+        TK::GreaterEqual => OpCode::GreaterEqual,
+        TK::Less => OpCode::Less,
+        TK::LessEqual => OpCode::LessEqual,
+        TK::Plus => OpCode::Add,
+        TK::Minus => OpCode::Subtract,
+        TK::Star => OpCode::Multiply,
+        TK::Slash => OpCode::Divide,
+        TK::Percent => OpCode::Modulo,
+        TK::StarStar => OpCode::Power,
+        TK::Ampersand => OpCode::BitAnd,
+        TK::Pipe => OpCode::BitOr,
+        TK::Caret => OpCode::BitXor,
+        TK::LessLess => OpCode::ShiftLeft,
+        TK::GreaterGreater => OpCode::ShiftRight,
+        _ => panic!("Invalid binary operator `{:?}`", kind),
+    }
+}
@@ -0,0 +1,985 @@
+pub mod chunk;
+pub mod compile_error;
+pub mod compiler;
+
+use std::{cell::RefCell, collections::HashMap, rc::Rc};
+
+use self::{
+    chunk::{OpCode, Proto},
+    compile_error::CompileError,
+    compiler::compile,
+};
+use crate::{
+    interpreter::{
+        callable::Callable,
+        class::{Class, Instance, INITIALIZER},
+        function::{Body, Closure, Function, Upvalue, MAX_FRAMES},
+        native::{NativeFunction, BUILTINS},
+        ops,
+        runtime_error::*,
+        value::Value,
+    },
+    parser::stmt::Stmt,
+    scanner::{span::Span, token::Token, token_kind::TokenKind as TK},
+};
+
+// Where to resume when an error is raised inside a `try` block.
+struct Handler {
+    // How many frames and values there were when the block was entered, which is what
+    // unwinding goes back to.
+    frames: usize,
+    stack: usize,
+    // Where the code handling the error starts, in the innermost of those frames.
+    ip: usize,
+}
+
+/// The names of global variables, numbered in the order they are first seen.
+/// Compiled code refers to globals by their number, so every program a VM runs must be
+/// compiled with its names.
+#[derive(Default)]
+pub struct GlobalNames {
+    names: Vec<String>,
+    indices: HashMap<String, u16>,
+}
+
+impl GlobalNames {
+    /// Returns the number of the global called `name`, numbering it first if it is new.
+    /// Returns `None` if every number is already taken.
+    pub fn index(&mut self, name: &str) -> Option<u16> {
+        if let Some(&index) = self.indices.get(name) {
+            return Some(index);
+        }
+
+        let index = u16::try_from(self.names.len()).ok()?;
+        self.names.push(name.into());
+        self.indices.insert(name.into(), index);
+        Some(index)
+    }
+
+    pub fn name(&self, index: u16) -> &str {
+        &self.names[index as usize]
+    }
+}
+
+struct CallFrame {
+    closure: Rc<Closure>,
+    ip: usize,
+    // Where the frame's slot zero is on the stack.
+    base: usize,
+    // What the call is reported as in tracebacks, if not the function's own name.
+    // Initializers are reported as their class.
+    name: Option<String>,
+}
+
+/// A stack-based virtual machine running compiled programs.
+/// It is an alternative to `Interpreter` with the same behavior.
+pub struct Vm {
+    stack: Vec<Value>,
+    frames: Vec<CallFrame>,
+    names: GlobalNames,
+    // Indexed by global number. Globals that are named but not defined yet are `None`.
+    globals: Vec<Option<Value>>,
+    // Upvalues still pointing into the stack. Closures created while one of them is open
+    // must share it, so that they see each other's assignments.
+    open_upvalues: Vec<Rc<RefCell<Upvalue>>>,
+    // The `try` blocks being run, innermost last.
+    handlers: Vec<Handler>,
+}
+
+impl Default for Vm {
+    fn default() -> Self {
+        let mut vm = Self {
+            stack: Vec::new(),
+            frames: Vec::new(),
+            names: GlobalNames::default(),
+            globals: Vec::new(),
+            open_upvalues: Vec::new(),
+            handlers: Vec::new(),
+        };
+        for &(name, arity, fun) in BUILTINS {
+            vm.define_native(name, arity, fun);
+        }
+        vm
+    }
+}
+
+impl Vm {
+    /// Exposes a host function to scripts as a global called `name`, just like
+    /// `Interpreter::define_native`.
+    pub fn define_native(
+        &mut self,
+        name: &str,
+        arity: usize,
+        fun: impl Fn(Vec<Value>) -> Result<Value, String> + 'static,
+    ) {
+        let native = NativeFunction::new(name.into(), arity, Rc::new(fun));
+        let index = self
+            .names
+            .index(name)
+            .expect("There should be room for every native") as usize;
+
+        self.globals.resize(self.names.names.len(), None);
+        self.globals[index] = Some(Value::Native(Rc::new(native)));
+    }
+
+    /// Compiles a resolved program to be run by this VM, which knows its globals' numbers.
+    pub fn compile(&mut self, stmts: &[Stmt]) -> Result<Rc<Proto>, CompileError> {
+        compile(stmts, &mut self.names)
+    }
+
+    /// Runs a program returned by `compile`. Globals are kept between runs.
+    pub fn interpret(&mut self, script: Rc<Proto>) -> Result<(), RuntimeError> {
+        self.globals.resize(self.names.names.len(), None);
+
+        let closure = Rc::new(Closure {
+            proto: script,
+            upvalues: Vec::new(),
+        });
+
+        self.stack.push(Value::Nil);
+        self.frames.push(CallFrame {
+            closure,
+            ip: 0,
+            base: 0,
+            name: None,
+        });
+
+        self.run().map_err(|err| {
+            // The script itself is not a call, so it has no frame in the traceback.
+            let err = self.unwind_frames(err, 1);
+
+            self.stack.clear();
+            self.frames.clear();
+            self.open_upvalues.clear();
+            self.handlers.clear();
+            err
+        })
+    }
+
+    // Runs until the script returns, resuming at the innermost handler whenever an error
+    // is raised inside a `try` block.
+    fn run(&mut self) -> Result<(), RuntimeError> {
+        loop {
+            let Err(err) = self.dispatch() else {
+                return Ok(());
+            };
+
+            let Some(handler) = self.handlers.pop() else {
+                return Err(err);
+            };
+
+            // The calls being unwound are recorded, in case the error is thrown again.
+            let err = self.unwind_frames(err, handler.frames);
+
+            self.frames.truncate(handler.frames);
+            self.close_upvalues(handler.stack);
+            self.stack.truncate(handler.stack);
+            self.stack.push(Value::Error(Rc::new(err)));
+            self.frame_mut().ip = handler.ip;
+        }
+    }
+
+    // Executes instructions until the script returns or an error is raised.
+    fn dispatch(&mut self) -> Result<(), RuntimeError> {
+        loop {
+            // Spans are only looked up from here when an error is raised.
+            let start = self.frame().ip;
+            let op = OpCode::from_byte(self.read_byte());
+
+            match op {
+                OpCode::Constant => {
+                    let constant = self.read_constant();
+                    self.stack.push(constant);
+                }
+
+                OpCode::Nil => self.stack.push(Value::Nil),
+                OpCode::True => self.stack.push(Value::Bool(true)),
+                OpCode::False => self.stack.push(Value::Bool(false)),
+
+                OpCode::Pop => {
+                    self.pop();
+                }
+
+                OpCode::Dup => {
+                    let depth = self.read_byte() as usize;
+                    let value = self.stack[self.stack.len() - 1 - depth].clone();
+                    self.stack.push(value);
+                }
+
+                OpCode::Bury => {
+                    let depth = self.read_byte() as usize;
+                    let len = self.stack.len();
+                    self.stack[len - 1 - depth..].rotate_right(1);
+                }
+
+                OpCode::GetLocal => {
+                    let slot = self.frame().base + self.read_byte() as usize;
+                    self.stack.push(self.stack[slot].clone());
+                }
+
+                OpCode::SetLocal => {
+                    let slot = self.frame().base + self.read_byte() as usize;
+                    self.stack[slot] = self.peek().clone();
+                }
+
+                OpCode::GetGlobal => {
+                    let index = self.read_u16();
+                    match &self.globals[index as usize] {
+                        Some(value) => self.stack.push(value.clone()),
+                        None => return Err(self.undefined_global(index, start)),
+                    }
+                }
+
+                OpCode::DefineGlobal => {
+                    let index = self.read_u16() as usize;
+                    let value = self.pop();
+                    self.globals[index] = Some(value);
+                }
+
+                OpCode::SetGlobal => {
+                    let index = self.read_u16();
+                    let value = self.peek().clone();
+                    match &mut self.globals[index as usize] {
+                        Some(global) => *global = value,
+                        None => return Err(self.undefined_global(index, start)),
+                    }
+                }
+
+                OpCode::GetUpvalue => {
+                    let index = self.read_byte() as usize;
+                    let upvalue = Rc::clone(&self.frame().closure.upvalues[index]);
+                    let value = match &*upvalue.borrow() {
+                        Upvalue::Open(slot) => self.stack[*slot].clone(),
+                        Upvalue::Closed(value) => value.clone(),
+                    };
+                    self.stack.push(value);
+                }
+
+                OpCode::SetUpvalue => {
+                    let index = self.read_byte() as usize;
+                    let upvalue = Rc::clone(&self.frame().closure.upvalues[index]);
+                    let value = self.peek().clone();
+                    let mut upvalue = upvalue.borrow_mut();
+                    match &mut *upvalue {
+                        Upvalue::Open(slot) => self.stack[*slot] = value,
+                        Upvalue::Closed(closed) => *closed = value,
+                    }
+                }
+
+                OpCode::GetProperty => {
+                    let name = name_token(self.read_name(), self.span_at(start));
+                    match self.pop() {
+                        Value::Instance(instance) => {
+                            let value = Instance::get(&instance, &name)?;
+                            self.stack.push(value);
+                        }
+                        Value::Error(err) => self.stack.push(err.property(&name)?),
+                        value => return Err(not_an_instance(value, self.span_at(start))),
+                    }
+                }
+
+                OpCode::SetProperty => {
+                    let name = name_token(self.read_name(), self.span_at(start));
+                    let value = self.pop();
+                    match self.pop() {
+                        Value::Instance(instance) => {
+                            instance.borrow_mut().set(&name, value.clone());
+                            self.stack.push(value);
+                        }
+                        object => return Err(not_an_instance(object, self.span_at(start))),
+                    }
+                }
+
+                OpCode::GetSuper => {
+                    let method = name_token(self.read_name(), self.span_at(start));
+                    let Value::Class(superclass) = self.pop() else {
+                        panic!("`super` should always be bound to a class");
+                    };
+                    let Value::Instance(instance) = self.pop() else {
+                        panic!("`this` should always be bound to an instance");
+                    };
+
+                    match superclass.find_method(&method.lexeme) {
+                        Some(found) => self
+                            .stack
+                            .push(Value::Function(Rc::new(found.bind(&instance)))),
+                        None => {
+                            return Err(undefined_super_method(superclass.name().into(), method))
+                        }
+                    }
+                }
+
+                OpCode::Equal => self.binary(TK::EqualEqual, start)?,
+                OpCode::NotEqual => self.binary(TK::BangEqual, start)?,
+                OpCode::Greater => self.compare(TK::Greater, start, i64::gt, f64::gt)?,
+                OpCode::GreaterEqual => self.compare(TK::GreaterEqual, start, i64::ge, f64::ge)?,
+                OpCode::Less => self.compare(TK::Less, start, i64::lt, f64::lt)?,
+                OpCode::LessEqual => self.compare(TK::LessEqual, start, i64::le, f64::le)?,
+                OpCode::Add => self.arithmetic(TK::Plus, start, i64::checked_add, |l, r| l + r)?,
+                OpCode::Subtract => {
+                    self.arithmetic(TK::Minus, start, i64::checked_sub, |l, r| l - r)?
+                }
+                OpCode::Multiply => {
+                    self.arithmetic(TK::Star, start, i64::checked_mul, |l, r| l * r)?
+                }
+                OpCode::Divide => self.binary(TK::Slash, start)?,
+                OpCode::Modulo => self.binary(TK::Percent, start)?,
+                OpCode::Power => self.binary(TK::StarStar, start)?,
+                OpCode::BitAnd => self.binary(TK::Ampersand, start)?,
+                OpCode::BitOr => self.binary(TK::Pipe, start)?,
+                OpCode::BitXor => self.binary(TK::Caret, start)?,
+                OpCode::ShiftLeft => self.binary(TK::LessLess, start)?,
+                OpCode::ShiftRight => self.binary(TK::GreaterGreater, start)?,
+
+                OpCode::Not => {
+                    let r = self.pop();
+                    self.stack.push(Value::Bool(!r.is_truthy()));
+                }
+
+                OpCode::Negate => match self.pop() {
+                    Value::Int(n) if n != i64::MIN => self.stack.push(Value::Int(-n)),
+                    Value::Number(n) => self.stack.push(Value::Number(-n)),
+                    r => {
+                        let value = ops::unary(TK::Minus, r, self.span_at(start))?;
+                        self.stack.push(value);
+                    }
+                },
+
+                OpCode::BitNot => {
+                    let r = self.pop();
+                    self.stack
+                        .push(ops::unary(TK::Tilde, r, self.span_at(start))?);
+                }
+
+                OpCode::Print => println!("{}", self.pop()),
+
+                OpCode::Jump => {
+                    let offset = self.read_u16() as usize;
+                    self.frame_mut().ip += offset;
+                }
+
+                OpCode::JumpIfFalse => {
+                    let offset = self.read_u16() as usize;
+                    if !self.peek().is_truthy() {
+                        self.frame_mut().ip += offset;
+                    }
+                }
+
+                OpCode::Loop => {
+                    let offset = self.read_u16() as usize;
+                    self.frame_mut().ip -= offset;
+                }
+
+                OpCode::Call => {
+                    let arg_count = self.read_byte() as usize;
+                    self.call(arg_count, start)?;
+                }
+
+                OpCode::Closure => {
+                    let index = self.read_u16() as usize;
+                    let proto = Rc::clone(&self.frame().closure.proto.chunk.protos[index]);
+
+                    let mut upvalues = Vec::with_capacity(proto.upvalue_count);
+                    for _ in 0..proto.upvalue_count {
+                        let is_local = self.read_byte() == 1;
+                        let index = self.read_byte() as usize;
+
+                        let upvalue = if is_local {
+                            self.capture(self.frame().base + index)
+                        } else {
+                            Rc::clone(&self.frame().closure.upvalues[index])
+                        };
+                        upvalues.push(upvalue);
+                    }
+
+                    let is_initializer = proto.is_initializer;
+                    let closure = Rc::new(Closure { proto, upvalues });
+                    let fun = Function::compiled(closure, is_initializer);
+                    self.stack.push(Value::Function(Rc::new(fun)));
+                }
+
+                OpCode::CloseUpvalue => {
+                    self.close_upvalues(self.stack.len() - 1);
+                    self.pop();
+                }
+
+                OpCode::Return => {
+                    let result = self.pop();
+                    let frame = self.frames.pop().expect("Returns should be inside a frame");
+                    self.close_upvalues(frame.base);
+                    self.stack.truncate(frame.base);
+
+                    if self.frames.is_empty() {
+                        return Ok(());
+                    }
+
+                    self.stack.push(result);
+                }
+
+                OpCode::BuildList => {
+                    let count = self.read_u16() as usize;
+                    let items = self.stack.split_off(self.stack.len() - count);
+                    self.stack.push(Value::from(items));
+                }
+
+                OpCode::Concat => {
+                    let count = self.read_u16() as usize;
+                    let parts = self.stack.split_off(self.stack.len() - count);
+                    let string = parts.iter().map(Value::to_string).collect();
+                    self.stack.push(Value::String(string));
+                }
+
+                OpCode::BuildMap => {
+                    let count = self.read_u16() as usize;
+                    let entries = self.stack.split_off(self.stack.len() - 2 * count);
+                    let mut map = HashMap::with_capacity(count);
+                    for entry in entries.chunks_exact(2) {
+                        let key = ops::map_key(&entry[0], self.span_at(start))?;
+                        map.insert(key, entry[1].clone());
+                    }
+                    self.stack.push(Value::from(map));
+                }
+
+                OpCode::GetIndex => {
+                    let index = self.pop();
+                    let object = self.pop();
+                    self.stack
+                        .push(ops::index(object, index, self.span_at(start))?);
+                }
+
+                OpCode::SetIndex => {
+                    let value = self.pop();
+                    let index = self.pop();
+                    let object = self.pop();
+                    let value = ops::set_index(object, index, value, self.span_at(start))?;
+                    self.stack.push(value);
+                }
+
+                OpCode::CheckSuperclass => {
+                    let name = name_token(self.read_name(), self.span_at(start));
+                    if !matches!(self.peek(), Value::Class(_)) {
+                        return Err(superclass_not_class(name, self.peek().clone()));
+                    }
+                }
+
+                OpCode::Class => {
+                    let name = self.read_name();
+                    let method_count = self.read_byte() as usize;
+                    let has_superclass = self.read_byte() == 1;
+
+                    let methods: HashMap<String, Rc<Function>> = self
+                        .stack
+                        .split_off(self.stack.len() - method_count)
+                        .into_iter()
+                        .map(|method| match method {
+                            Value::Function(fun) => (fun.name().into(), fun),
+                            got => panic!("Methods should always be functions, got `{:?}`", got),
+                        })
+                        .collect();
+
+                    let superclass = match (has_superclass, self.peek()) {
+                        (true, Value::Class(superclass)) => Some(Rc::clone(superclass)),
+                        _ => None,
+                    };
+
+                    let class = Class::new(name, superclass, methods);
+                    self.stack.push(Value::Class(Rc::new(class)));
+                }
+
+                OpCode::PushHandler => {
+                    let offset = self.read_u16() as usize;
+                    self.handlers.push(Handler {
+                        frames: self.frames.len(),
+                        stack: self.stack.len(),
+                        ip: self.frame().ip + offset,
+                    });
+                }
+
+                OpCode::PopHandler => {
+                    self.handlers.pop();
+                }
+
+                OpCode::Throw => {
+                    return match self.pop() {
+                        Value::Error(err) => Err(err.as_ref().clone()),
+                        value => Err(thrown(value, self.span_at(start))),
+                    };
+                }
+            }
+        }
+    }
+
+    // Calls the callee sitting below the `arg_count` arguments on top of the stack, for the call
+    // instruction at `start`.
+    fn call(&mut self, arg_count: usize, start: usize) -> Result<(), RuntimeError> {
+        let slot = self.stack.len() - arg_count - 1;
+
+        match self.stack[slot].clone() {
+            Value::Function(fun) => self.call_function(&fun, arg_count, None, start),
+
+            Value::Class(class) => {
+                let instance = Rc::new(RefCell::new(Instance::new(Rc::clone(&class))));
+
+                match class.find_method(INITIALIZER) {
+                    Some(init) => {
+                        let init = init.bind(&instance);
+                        self.call_function(&init, arg_count, Some(class.name().into()), start)
+                    }
+                    None if arg_count != 0 => {
+                        Err(arity_mismatch(0, arg_count, self.span_at(start)))
+                    }
+                    None => {
+                        self.stack[slot] = Value::Instance(instance);
+                        Ok(())
+                    }
+                }
+            }
+
+            Value::Native(native) => {
+                let span = self.span_at(start);
+                if arg_count != native.arity() {
+                    return Err(arity_mismatch(native.arity(), arg_count, span));
+                }
+
+                let args = self.stack.split_off(slot + 1);
+                let result = native
+                    .call(args, span)
+                    .map_err(|err| err.with_frame(native.name().into(), span))?;
+                self.stack[slot] = result;
+                Ok(())
+            }
+
+            callee => Err(not_callable(callee, self.span_at(start))),
+        }
+    }
+
+    // Starts running a compiled function in a new frame, reported as `name` if given.
+    fn call_function(
+        &mut self,
+        fun: &Function,
+        arg_count: usize,
+        name: Option<String>,
+        start: usize,
+    ) -> Result<(), RuntimeError> {
+        let Body::Compiled { closure, receiver } = fun.body() else {
+            unreachable!("The VM should only ever create compiled functions");
+        };
+
+        if arg_count != closure.proto.arity {
+            let span = self.span_at(start);
+            return Err(arity_mismatch(closure.proto.arity, arg_count, span));
+        }
+
+        // The call that overflows is part of the traceback, like in the tree-walker.
+        if self.frames.len() == MAX_FRAMES {
+            let span = self.span_at(start);
+            let name = name.unwrap_or_else(|| closure.proto.name.clone());
+            return Err(stack_overflow(span).with_frame(name, span));
+        }
+
+        let base = self.stack.len() - arg_count - 1;
+        if let Some(receiver) = receiver {
+            self.stack[base] = Value::Instance(Rc::clone(receiver));
+        }
+
+        self.frames.push(CallFrame {
+            closure: Rc::clone(closure),
+            ip: 0,
+            base,
+            name,
+        });
+
+        Ok(())
+    }
+
+    fn binary(&mut self, op: TK, start: usize) -> Result<(), RuntimeError> {
+        let r = self.pop();
+        let l = self.pop();
+        self.stack.push(ops::binary(op, l, r, self.span_at(start))?);
+        Ok(())
+    }
+
+    // Applies an arithmetic operator to the two values on top of the stack, in place.
+    // Numbers of the same type are handled right here, and everything else, overflows
+    // included, by `ops::binary`.
+    #[inline(always)]
+    fn arithmetic(
+        &mut self,
+        op: TK,
+        start: usize,
+        ints: fn(i64, i64) -> Option<i64>,
+        floats: fn(f64, f64) -> f64,
+    ) -> Result<(), RuntimeError> {
+        let len = self.stack.len();
+        let result = match (&self.stack[len - 2], &self.stack[len - 1]) {
+            (Value::Int(l), Value::Int(r)) => match ints(*l, *r) {
+                Some(n) => Value::Int(n),
+                None => return self.binary(op, start),
+            },
+            (Value::Number(l), Value::Number(r)) => Value::Number(floats(*l, *r)),
+            _ => return self.binary(op, start),
+        };
+
+        self.stack.truncate(len - 1);
+        self.stack[len - 2] = result;
+        Ok(())
+    }
+
+    // Like `arithmetic`, for comparison operators.
+    #[inline(always)]
+    fn compare(
+        &mut self,
+        op: TK,
+        start: usize,
+        ints: fn(&i64, &i64) -> bool,
+        floats: fn(&f64, &f64) -> bool,
+    ) -> Result<(), RuntimeError> {
+        let len = self.stack.len();
+        let result = match (&self.stack[len - 2], &self.stack[len - 1]) {
+            (Value::Int(l), Value::Int(r)) => ints(l, r),
+            (Value::Number(l), Value::Number(r)) => floats(l, r),
+            _ => return self.binary(op, start),
+        };
+
+        self.stack.truncate(len - 1);
+        self.stack[len - 2] = Value::Bool(result);
+        Ok(())
+    }
+
+    // Returns where the instruction starting at `start` in the current frame came from.
+    fn span_at(&self, start: usize) -> Span {
+        self.frame().closure.proto.chunk.spans[start]
+    }
+
+    fn undefined_global(&self, index: u16, start: usize) -> RuntimeError {
+        let name = self.names.name(index).into();
+        undefined_variable(name_token(name, self.span_at(start)))
+    }
+
+    // Records the frames from `from` up in the error's traceback, innermost first.
+    // Each one was called by the frame right below it, which is still on its call instruction.
+    fn unwind_frames(&self, err: RuntimeError, from: usize) -> RuntimeError {
+        (from..self.frames.len()).rev().fold(err, |err, index| {
+            let frame = &self.frames[index];
+            let caller = &self.frames[index - 1];
+            let name = frame
+                .name
+                .clone()
+                .unwrap_or_else(|| frame.closure.proto.name.clone());
+
+            err.with_frame(name, caller.closure.proto.chunk.spans[caller.ip - 1])
+        })
+    }
+
+    // Returns the upvalue for the stack slot, reusing the open one if it was already captured.
+    fn capture(&mut self, slot: usize) -> Rc<RefCell<Upvalue>> {
+        let open = self
+            .open_upvalues
+            .iter()
+            .find(|upvalue| matches!(*upvalue.borrow(), Upvalue::Open(open) if open == slot));
+
+        if let Some(upvalue) = open {
+            return Rc::clone(upvalue);
+        }
+
+        let upvalue = Rc::new(RefCell::new(Upvalue::Open(slot)));
+        self.open_upvalues.push(Rc::clone(&upvalue));
+        upvalue
+    }
+
+    // Moves the values of every upvalue pointing at `from` or above off the stack.
+    fn close_upvalues(&mut self, from: usize) {
+        let stack = &self.stack;
+
+        self.open_upvalues.retain(|upvalue| {
+            let mut upvalue = upvalue.borrow_mut();
+            match *upvalue {
+                Upvalue::Open(slot) if slot >= from => {
+                    *upvalue = Upvalue::Closed(stack[slot].clone());
+                    false
+                }
+                _ => true,
+            }
+        });
+    }
+
+    fn frame(&self) -> &CallFrame {
+        self.frames
+            .last()
+            .expect("The VM should be running a frame")
+    }
+
+    fn frame_mut(&mut self) -> &mut CallFrame {
+        self.frames
+            .last_mut()
+            .expect("The VM should be running a frame")
+    }
+
+    fn read_byte(&mut self) -> u8 {
+        let frame = self.frame_mut();
+        let byte = frame.closure.proto.chunk.code[frame.ip];
+        frame.ip += 1;
+        byte
+    }
+
+    fn read_u16(&mut self) -> u16 {
+        let frame = self.frame_mut();
+        let value = frame.closure.proto.chunk.read_u16(frame.ip);
+        frame.ip += 2;
+        value
+    }
+
+    fn read_constant(&mut self) -> Value {
+        let index = self.read_u16() as usize;
+        self.frame().closure.proto.chunk.constants[index].clone()
+    }
+
+    fn read_name(&mut self) -> String {
+        match self.read_constant() {
+            Value::String(name) => name,
+            got => panic!("Names should always be string constants, got `{:?}`", got),
+        }
+    }
+
+    fn peek(&self) -> &Value {
+        self.stack.last().expect("The stack should not be empty")
+    }
+
+    fn pop(&mut self) -> Value {
+        self.stack.pop().expect("The stack should not be empty")
+    }
+}
+
+// Errors about names expect the token they were written as.
+fn name_token(name: String, span: Span) -> Token {
+    Token::symbol(TK::Identifier, name, span)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, rc::Rc};
+
+    use super::{compile_error::CompileError, Vm};
+    use crate::{
+        interpreter::{runtime_error::RuntimeError, value::Value, Interpreter},
+        parser::{parse, stmt::Stmt},
+        resolver::resolve,
+        scanner::tokenize,
+    };
+
+    fn run(source: &str) -> Vm {
+        let mut vm = Vm::default();
+        let script = vm.compile(&parse_resolved(source)).unwrap();
+        vm.interpret(script).unwrap();
+        vm
+    }
+
+    fn parse_resolved(source: &str) -> Vec<Stmt> {
+        let mut stmts = parse(tokenize(source.into()).unwrap()).unwrap();
+        resolve(&mut stmts).unwrap();
+        stmts
+    }
+
+    fn global(vm: &Vm, name: &str) -> Value {
+        let index = vm.names.indices[name] as usize;
+        vm.globals[index].clone().unwrap()
+    }
+
+    #[test]
+    fn test_closures() {
+        // Each iteration declares a new `j`, while `i` is shared by the whole loop.
+        let vm = run("
+            var first;
+            var second;
+            var shared;
+            for (var i = 0; i < 2; i = i + 1) {
+                var j = i;
+                fun get() {
+                    return j;
+                }
+                fun getShared() {
+                    return i;
+                }
+                if (i == 0) first = get; else second = get;
+                shared = getShared;
+            }
+
+            var result = first() + second() * 10 + shared() * 100;
+        ");
+
+        assert_eq!(global(&vm, "result"), Value::Int(210));
+    }
+
+    #[test]
+    fn test_classes() {
+        let vm = run("
+            class A {
+                init(x) {
+                    this.x = x;
+                }
+
+                name() {
+                    return \"A\";
+                }
+            }
+
+            class B < A {
+                init(x) {
+                    super.init(x * 2);
+                }
+
+                name() {
+                    return \"B\" + super.name() + this.x;
+                }
+            }
+
+            var result = B(2).name();
+        ");
+
+        assert_eq!(global(&vm, "result"), Value::from("BA4"));
+    }
+
+    #[test]
+    fn test_jumps() {
+        let vm = run("
+            var result = \"\";
+            outer: for (var a = 0; a < 3; a = a + 1) {
+                for (var b = 0; b < 3; b = b + 1) {
+                    var pair = \"\" + a + b;
+                    fun get() {
+                        return pair;
+                    }
+
+                    if (b == 1) continue outer;
+                    if (a == 2) break outer;
+                    result = result + get() + \" \";
+                }
+            }
+        ");
+
+        assert_eq!(global(&vm, "result"), Value::from("00 10 "));
+    }
+
+    #[test]
+    fn test_exceptions() {
+        // Every way out of the try block runs the finally block exactly once.
+        let vm = run("
+            var log = \"\";
+            fun leave(how) {
+                for (var i = 0; i < 2; i++) {
+                    var kept = how;
+                    try {
+                        if (how == \"break\") break;
+                        if (how == \"return\") return kept;
+                        throw kept;
+                    } catch (e) {
+                        if (how == \"rethrow\") throw e;
+                        return e.value;
+                    } finally {
+                        log = log + how + \" \";
+                    }
+                }
+                return \"after\";
+            }
+
+            var results = leave(\"break\") + leave(\"return\") + leave(\"throw\");
+            try { leave(\"rethrow\"); } catch (e) { results = results + e.value; }
+        ");
+
+        assert_eq!(
+            global(&vm, "log"),
+            Value::from("break return throw rethrow ")
+        );
+        assert_eq!(
+            global(&vm, "results"),
+            Value::from("afterreturnthrowrethrow")
+        );
+    }
+
+    #[test]
+    fn test_too_many_methods() {
+        let methods: String = (0..256).map(|i| format!("m{i}() {{}} ")).collect();
+        let source = format!("class A {{ {methods} }}");
+
+        let error = Vm::default().compile(&parse_resolved(&source)).unwrap_err();
+        assert!(matches!(error, CompileError::TooManyMethods { .. }));
+        assert_eq!(error.message(), "Too many methods in one class");
+    }
+
+    #[test]
+    fn test_nil_comparisons() {
+        let source = "
+            class Node {
+                init(next) {
+                    this.next = next;
+                }
+            }
+
+            var list = Node(Node(nil));
+            var length = 0;
+            for (var node = list; node != nil; node = node.next) length++;
+            log(length);
+            log(list == nil);
+            log(nil == 0);
+            log(\"\" != nil);
+            log(nil == nil);
+        ";
+        let expected = ["2", "false", "false", "true", "true"];
+
+        let logger = || {
+            let logged = Rc::new(RefCell::new(Vec::new()));
+            let log = Rc::clone(&logged);
+            let native = move |args: Vec<Value>| {
+                log.borrow_mut().push(args[0].to_string());
+                Ok(Value::Nil)
+            };
+            (logged, native)
+        };
+
+        let (logged, log) = logger();
+        let mut vm = Vm::default();
+        vm.define_native("log", 1, log);
+        let script = vm.compile(&parse_resolved(source)).unwrap();
+        vm.interpret(script).unwrap();
+        assert_eq!(*logged.borrow(), expected);
+
+        let (logged, log) = logger();
+        let mut interpreter = Interpreter::default();
+        interpreter.define_native("log", 1, log);
+        interpreter.interpret(&parse_resolved(source)).unwrap();
+        assert_eq!(*logged.borrow(), expected);
+    }
+
+    #[test]
+    fn test_errors_match_interpreter() {
+        let sources = [
+            "fun f() { return 1 / 0; } class K { m() { return f(); } } K().m();",
+            "class A { init(x) {} } fun f() { A(); } f();",
+            "var x = 1; class B < x {}",
+            "class A {} class B < A { m() { return super.nope(); } } B().m();",
+            "fun f() { return undefined; } f();",
+            "var xs = [1, 2]; xs[-3] = 0;",
+            "insert([], 1, 0);",
+            "var m = {\"a\": 1}; print m[\"b\"];",
+            "var m = {[]: 1};",
+            "print -9223372036854775807 - 2;",
+            "print 1 << 64;",
+            "fun f() { throw \"up\"; } fun g() { try { f(); } finally { print 1; } } g();",
+            "fun f() { try { 1 / 0; } catch (e) { throw e; } } f();",
+            "fun f() { f(); } f();",
+            "class A { init() { A(); } } A();",
+        ];
+
+        for source in sources {
+            let mut vm = Vm::default();
+            let script = vm.compile(&parse_resolved(source)).unwrap();
+            let in_vm = vm.interpret(script).unwrap_err();
+            let in_tree = Interpreter::default()
+                .interpret(&parse_resolved(source))
+                .unwrap_err();
+
+            let describe = |err: &RuntimeError| (err.message(), err.span(), err.traceback());
+            assert_eq!(describe(&in_vm), describe(&in_tree), "{source}");
+        }
+    }
+}